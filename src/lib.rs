@@ -0,0 +1,2332 @@
+use std::env;
+use std::io::{self, BufRead, BufReader, Error, BufWriter, Write};
+use std::fs::{self, File, OpenOptions};
+use std::path::{self, Component, Path, PathBuf};
+use std::fmt;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicU8, Ordering as AtomicOrdering};
+
+// The field order matters: the derived ordering compares year, then month, then day.
+#[derive(Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+// Time of day that may follow the date in a revdate, e.g. `2025-06-01 14:30`.
+#[derive(Clone, Copy, Default)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Time {
+    pub hour: u8,
+    pub minute: u8,
+}
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+// Ordinal of 1970-01-01.
+const UNIX_EPOCH_ORDINAL: i64 = 719468;
+
+impl Date {
+    // Number of days since 0000-03-01 in the proleptic Gregorian calendar.
+    // Counting years from March puts the leap day at the end of the year,
+    // which keeps the math below free of special cases.
+    fn to_ordinal(self) -> i64 {
+        let year = if self.month <= 2 { self.year as i64 - 1 } else { self.year as i64 };
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let mp = (self.month as i64 + 9) % 12;
+        let doy = (153 * mp + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146097 + doe
+    }
+
+    // Inverse of to_ordinal. Years outside of what a Date can hold are clamped.
+    fn from_ordinal(ordinal: i64) -> Date {
+        let era = ordinal.div_euclid(146097);
+        let doe = ordinal.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        Date { year: year.clamp(0, u16::MAX as i64) as u16, month: month as u8, day: day as u8 }
+    }
+
+    // The current date in UTC.
+    pub fn today() -> Date {
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        Date::from_ordinal(UNIX_EPOCH_ORDINAL + secs as i64 / 86400)
+    }
+
+    fn add_days(self, days: i64) -> Date {
+        Date::from_ordinal(self.to_ordinal() + days)
+    }
+
+    // Same day N months earlier, or the last day of that month if it's shorter.
+    fn sub_months(self, months: u32) -> Date {
+        let index = self.year as i64 * 12 + self.month as i64 - 1 - months as i64;
+        if index < 0 {
+            return Date { year: 0, month: 1, day: 1 };
+        }
+
+        let year = (index / 12) as u16;
+        let month = (index % 12) as u8 + 1;
+        Date { year, month, day: self.day.min(days_in_month(year, month)) }
+    }
+}
+
+// Start date for --since: Nd, Nw or Nm before today.
+pub fn parse_since(text: &str, today: Date) -> io::Result<Date> {
+    let invalid = || error(format!("--since expects a number followed by d, w or m (e.g. 14d), got '{}'.", text));
+
+    let Some((split, _)) = text.char_indices().last() else {
+        return Err(invalid());
+    };
+    let (count, unit) = text.split_at(split);
+    let count: u32 = count.parse().map_err(|_| invalid())?;
+
+    match unit {
+        "d" => Ok(today.add_days(-(count as i64))),
+        "w" => Ok(today.add_days(-(count as i64) * 7)),
+        "m" => Ok(today.sub_months(count)),
+        _ => Err(invalid()),
+    }
+}
+
+pub struct Doc {
+    pub path: String,
+    pub revdate: Option<Date>,
+    // Only set along with the revdate, when it has a time.
+    pub revtime: Option<Time>,
+    pub content: String,
+    // The level-0 heading (`= Title`), without the `= `.
+    pub title: Option<String>,
+    pub id: String,
+    pub author: Option<String>,
+    pub mtime: Option<SystemTime>,
+    // Names of all attribute entries in the document, in order of appearance.
+    pub attributes: Vec<String>,
+    // From `:tags: a, b` in the header.
+    pub tags: Vec<String>,
+    pub has_imagesdir: bool,
+    // The raw bytes of documents read from an archive, which have no file
+    // on disk to read them back from.
+    pub archived: Option<Vec<u8>>,
+}
+
+// How much the library writes to stderr while it works: warnings about the
+// documents, and with Verbose what happened to each file. Errors are always
+// returned instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, AtomicOrdering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(AtomicOrdering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+// Per-file diagnostics for Verbosity::Verbose.
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if verbosity() == Verbosity::Verbose {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+pub fn error(text: String) -> Error {
+    Error::other(text)
+}
+
+pub fn warning(text: String) {
+    if verbosity() == Verbosity::Quiet {
+        return;
+    }
+    eprintln!("Warning: {}", text);
+}
+
+pub fn error_with_file(path: &Path, err: Error) -> Error {
+    Error::other(format!("{}: {}", path.display(), err))
+}
+
+pub fn error_with_file_and_line(path: &Path, line: usize, err: Error) -> Error {
+    Error::other(format!("{}:{}: {}", path.display(), line + 1, err))
+}
+
+fn is_leap_year(year: u16) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+pub fn try_parse_date(date: &str) -> io::Result<Date> {
+        let len = 4 + 1 + 2 + 1 + 2;
+        let mut ok = date.len() == len;
+
+        let mut year = 0u16;
+        let mut month = 0u8;
+        let mut day = 0u8;
+
+        if ok {
+            let date = date.as_bytes();
+            ok = date[4] == b'-' && date[7] == b'-';
+        }
+
+        if ok {
+            year = date[0..=3].parse().unwrap_or_else(|_| { ok = false; 0 });
+            month = date[5..=6].parse().unwrap_or_else(|_| { ok = false; 0 });
+            day = date[8..=9].parse().unwrap_or_else(|_| { ok = false; 0 });
+
+            ok = year > 0 && (1..=12).contains(&month) && (1..=31).contains(&day);
+        }
+
+        if !ok {
+            return Err(error(format!("Could not parse date '{}'", date)));
+        }
+
+        if day > days_in_month(year, month) {
+            return Err(error(format!("Invalid day {:02} for month {:02} in date '{}'", day, month, date)));
+        }
+
+        Ok(Date {year, month, day})
+}
+
+// HH:MM, 00:00 to 23:59.
+pub fn try_parse_time(time: &str) -> io::Result<Time> {
+    let (hour, minute) = time.split_once(':').unwrap_or(("", ""));
+    let (Ok(hour), Ok(minute)) = (hour.parse::<u8>(), minute.parse::<u8>()) else {
+        return Err(error(format!("Could not parse time '{}'", time)));
+    };
+
+    if hour > 23 || minute > 59 {
+        return Err(error(format!("Invalid time '{}'", time)));
+    }
+
+    Ok(Time {hour, minute})
+}
+
+// Like try_parse_date, but also takes a bare year (YYYY) or month (YYYY-MM),
+// which stand for their first day as a start bound and their last day as an end bound.
+pub fn try_parse_date_bound(date: &str, end: bool) -> io::Result<Date> {
+    let is_number = |text: &str| !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit());
+
+    let (year, month) = match date.split_once('-') {
+        None if date.len() == 4 && is_number(date) => (date, None),
+        Some((year, month)) if year.len() == 4 && month.len() == 2 && is_number(year) && is_number(month) => (year, Some(month)),
+        _ => return try_parse_date(date),
+    };
+
+    let year: u16 = year.parse().unwrap_or(0);
+    if year == 0 {
+        return Err(error(format!("Could not parse date '{}'", date)));
+    }
+
+    let month = match month {
+        Some(month) => match month.parse::<u8>() {
+            Ok(month) if (1..=12).contains(&month) => Some(month),
+            _ => return Err(error(format!("Invalid month in date '{}'", date))),
+        },
+        None => None,
+    };
+
+    Ok(match (month, end) {
+        (Some(month), false) => Date { year, month, day: 1 },
+        (Some(month), true) => Date { year, month, day: days_in_month(year, month) },
+        (None, false) => Date { year, month: 1, day: 1 },
+        (None, true) => Date { year, month: 12, day: 31 },
+    })
+}
+
+pub const MONTH_NAMES: [&str; 12] = [
+    "january", "february", "march", "april", "may", "june",
+    "july", "august", "september", "october", "november", "december",
+];
+
+// Parses the Asciidoctor style `1 June 2025` / `01 Jun 2025`.
+// Returns None when the text doesn't have that shape at all.
+fn try_parse_long_date(date: &str) -> io::Result<Option<Date>> {
+    let parts: Vec<&str> = date.split_whitespace().collect();
+    if parts.len() != 3 {
+        return Ok(None);
+    }
+
+    let day: u8 = match parts[0].parse() {
+        Ok(day) => day,
+        Err(_) => return Ok(None),
+    };
+    let year: u16 = match parts[2].parse() {
+        Ok(year) if parts[2].len() == 4 => year,
+        _ => return Ok(None),
+    };
+    if !parts[1].chars().all(|c| c.is_ascii_alphabetic()) {
+        return Ok(None);
+    }
+
+    let name = parts[1].to_ascii_lowercase();
+    let month = MONTH_NAMES
+        .iter()
+        .position(|m| *m == name || (name.len() == 3 && m.starts_with(&name)));
+    let month = match month {
+        Some(i) => i as u8 + 1,
+        None => return Err(error(format!("Unknown month name '{}' in date '{}'", parts[1], date))),
+    };
+
+    if year == 0 || day == 0 || day > days_in_month(year, month) {
+        return Err(error(format!("Invalid day {:02} for month {:02} in date '{}'", day, month, date)));
+    }
+
+    Ok(Some(Date {year, month, day}))
+}
+
+fn try_parse_date_attribute(line: &str, name: &str) -> io::Result<Option<(Date, Option<Time>)>> {
+    if let Some(date) = attribute_value(line, name) {
+        // A remark may follow the date, e.g. `2025-06-01: fixed typos`.
+        let iso = match date.get(..10) {
+            Some(head) if date[10..].starts_with(|c: char| c.is_whitespace() || c == ':') => head,
+            _ => date,
+        };
+
+        match try_parse_date(iso) {
+            Ok(d) => {
+                // So may a time, e.g. `2025-06-01 14:30`, which can have a
+                // remark of its own. Anything not shaped like HH:MM is part
+                // of the remark.
+                let rest = date[iso.len()..].trim_start();
+                let next = match rest.get(..5) {
+                    Some(head) if rest[5..].is_empty() || rest[5..].starts_with(|c: char| c.is_whitespace() || c == ':') => head,
+                    _ => "",
+                };
+                let is_time = next.len() == 5 && next.bytes().enumerate().all(|(i, b)| {
+                    if i == 2 { b == b':' } else { b.is_ascii_digit() }
+                });
+                let time = if is_time { Some(try_parse_time(next)?) } else { None };
+                Ok(Some((d, time)))
+            }
+            Err(e) => match try_parse_long_date(date) {
+                Ok(Some(d)) => Ok(Some((d, None))),
+                Ok(None) => Err(e),
+                Err(e) => Err(e),
+            },
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+// Name of the attribute set or unset by an attribute entry line, e.g.
+// `:revdate: 2025-06-01` or `:!sectnums:`.
+fn attribute_name(line: &str) -> Option<&str> {
+    let rest = line.strip_prefix(':')?;
+    let end = rest.find(':')?;
+    let name = rest[..end].trim_start_matches('!').trim_end_matches('!');
+
+    let valid = !name.is_empty() && name
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+
+    if valid { Some(name) } else { None }
+}
+
+// Value of an attribute entry like `:name: value`. Any whitespace may
+// separate the name from the value, like Asciidoctor allows.
+// Entries without a value give None.
+fn attribute_value<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let rest = line.strip_prefix(':')?.strip_prefix(name)?.strip_prefix(':')?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let value = rest.trim();
+    if value.is_empty() { None } else { Some(value) }
+}
+
+// Name of the first author in an author line like
+// `Jane Doe <jane@example.com>; John Doe`.
+fn byline_author_name(line: &str) -> Option<String> {
+    let first = line.split(';').next().unwrap_or("");
+    let name = first.split('<').next().unwrap_or("").trim();
+    if name.is_empty() { None } else { Some(name.to_string()) }
+}
+
+// Whether an image macro target is a path relative to the imagesdir.
+fn is_relative_image_target(target: &str) -> bool {
+    !target.is_empty()
+        && !target.contains(char::is_whitespace)
+        && !target.contains(['{', '}'])
+        && !target.contains("://")
+        && !target.starts_with("data:")
+        && !target.starts_with('/')
+        && Path::new(target).is_relative()
+}
+
+// Prefixes the relative targets of the image:: and image: macros in the line
+// with the directory.
+fn rewrite_image_targets(line: &str, dir: &str) -> String {
+    let mut rewritten = String::with_capacity(line.len() + dir.len());
+    let mut rest = line;
+    while let Some(at) = rest.find("image:") {
+        // Only a macro if it doesn't continue a word, like in myimage:.
+        let is_macro = !rest[..at].ends_with(|c: char| c.is_alphanumeric() || c == '_');
+
+        let mut end = at + "image:".len();
+        if rest[end..].starts_with(':') {
+            end += 1;
+        }
+        rewritten.push_str(&rest[..end]);
+        rest = &rest[end..];
+
+        let target = rest.find('[').map(|bracket| &rest[..bracket]).unwrap_or("");
+        if is_macro && is_relative_image_target(target) {
+            rewritten.push_str(dir);
+            rewritten.push('/');
+        }
+    }
+    rewritten.push_str(rest);
+    rewritten
+}
+
+// Directory the relative image targets of the document point into: its own
+// directory, or the local imagesdir it sets. None for imagesdirs that are URLs
+// or have attribute references, which can't be resolved here.
+fn image_dir(path: &Path, imagesdir: Option<&str>) -> io::Result<Option<String>> {
+    let parent = parent_dir(path)?;
+    let dir = match imagesdir {
+        Some(dir) if dir.contains(['{', '}']) || dir.contains("://") => return Ok(None),
+        Some(dir) => parent.join(dir),
+        None => parent.to_path_buf(),
+    };
+    let dir = normalize_path(&path::absolute(dir)?);
+    Ok(Some(forward_slashes(&dir.to_string_lossy()).trim_end_matches('/').to_string()))
+}
+
+static BOM: &str = unsafe { std::str::from_utf8_unchecked(&[0xEF, 0xBB, 0xBF]) };
+
+#[derive(Clone)]
+pub struct ParseOptions {
+    pub replace_images_with_links: bool,
+    // Make the relative targets of image macros absolute, so they don't
+    // depend on the imagesdir.
+    pub rewrite_image_paths: bool,
+    pub keep_bom: bool,
+    // When set, :imagesdir: entries are dropped from the documents and this
+    // value is used instead.
+    pub force_imagesdir: Option<String>,
+    // Absolute directory the rewritten imagesdirs are made relative to.
+    pub imagesdir_base: Option<PathBuf>,
+    // Warn about :revdate: entries after the document header. They are
+    // ignored either way, like Asciidoctor does.
+    pub warn_misplaced_revdate: bool,
+    // Splice included files into the documents instead of dropping the
+    // documents that have include:: directives.
+    pub follow_includes: bool,
+    // Keep \r\n line endings in the content instead of turning them into \n.
+    pub preserve_line_endings: bool,
+}
+
+// Where the lines of a document are with respect to comments.
+#[derive(Clone, Copy, PartialEq)]
+enum CommentState {
+    Outside,
+    // Inside a comment block delimited by this many slashes (at least four).
+    // Only the same number of slashes closes it, so a //// inside a ////// is
+    // just a comment line.
+    Block(usize),
+    // Right after a [comment] line.
+    Style,
+    // In the paragraph after [comment], up to the first blank line.
+    Paragraph,
+    // In the -- open block after [comment], up to the closing --.
+    OpenBlock,
+}
+
+impl CommentState {
+    // Moves past the (trimmed) line and returns whether it's part of a comment.
+    // The delimiter lines count as part of it.
+    fn advance(&mut self, line: &str) -> bool {
+        let slashes = (line.len() >= 4 && line.bytes().all(|b| b == b'/')).then_some(line.len());
+
+        *self = match (*self, slashes) {
+            (CommentState::Outside, Some(n)) => CommentState::Block(n),
+            (CommentState::Outside, None) if line == "[comment]" => CommentState::Style,
+            (CommentState::Outside, None) => return false,
+            (CommentState::Block(open), Some(n)) if n == open => CommentState::Outside,
+            (CommentState::Block(open), _) => CommentState::Block(open),
+            (CommentState::Style, Some(n)) => CommentState::Block(n),
+            (CommentState::Style, None) if line == "--" => CommentState::OpenBlock,
+            (CommentState::Style | CommentState::Paragraph, _) if line.is_empty() => {
+                *self = CommentState::Outside;
+                return false;
+            }
+            (CommentState::Style | CommentState::Paragraph, _) => CommentState::Paragraph,
+            (CommentState::OpenBlock, _) if line == "--" => CommentState::Outside,
+            (CommentState::OpenBlock, _) => CommentState::OpenBlock,
+        };
+        true
+    }
+
+    // The comment left open at the end of a file, if any.
+    fn unterminated(self) -> Option<&'static str> {
+        match self {
+            CommentState::Block(_) => Some("////"),
+            CommentState::OpenBlock => Some("[comment] --"),
+            _ => None,
+        }
+    }
+}
+
+// Same limit as Asciidoctor's max-include-depth.
+const MAX_INCLUDE_DEPTH: usize = 64;
+
+// Contents of the file an include:: directive in `from` points to, with the
+// include:: directives in it expanded too. Returns None (after a warning) when
+// the directive can't be followed and has to stay as it is.
+// `stack` holds the canonical paths of the files being included, outermost first.
+fn resolve_include(from: &Path, line: &str, stack: &mut Vec<PathBuf>) -> Option<String> {
+    let target = line.strip_prefix("include::")?;
+    let target = &target[..target.rfind('[')?];
+
+    if target.contains('{') || target.contains("://") {
+        warning(format!("{}: Can't follow include::{}[], leaving it as it is.", from.display(), target));
+        return None;
+    }
+
+    let path = from.parent().unwrap_or(Path::new("")).join(target);
+    let read = fs::canonicalize(&path).and_then(|canonical| {
+        if stack.contains(&canonical) {
+            return Err(error(String::from("Include cycle, not following it.")));
+        }
+        if stack.len() >= MAX_INCLUDE_DEPTH {
+            return Err(error(format!("Includes nested more than {} levels deep, not following it.", MAX_INCLUDE_DEPTH)));
+        }
+        Ok((canonical, fs::read_to_string(&path)?))
+    });
+
+    let text = match read {
+        Ok((canonical, text)) => {
+            stack.push(canonical);
+            text
+        }
+        Err(err) => {
+            warning(format!("{}", error_with_file(&path, err)));
+            return None;
+        }
+    };
+
+    let mut content = String::with_capacity(text.len());
+    let mut comment = CommentState::Outside;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if !comment.advance(trimmed) && trimmed.starts_with("include::") {
+            if let Some(included) = resolve_include(&path, trimmed, stack) {
+                content.push_str(&included);
+                continue;
+            }
+        }
+        content.push_str(line);
+    }
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+
+    stack.pop();
+    Some(content)
+}
+
+// None means the document is left out: it's empty, or it has include::
+// directives and they aren't followed.
+pub fn parse_doc(path: &Path, opts: &ParseOptions) -> io::Result<Option<Doc>> {
+    let file = File::open(path);
+    if let Err(err) = file {
+        return Err(error_with_file(path, err));
+    }
+    let file = file?;
+    let mtime = file.metadata().and_then(|m| m.modified()).ok();
+
+    parse_doc_from(path, BufReader::new(file), mtime, opts)
+}
+
+// A document read from a .zip archive. Its path is the one inside the
+// archive, so the imagesdir written for it is too. Includes can't be
+// followed and image targets can't be made absolute.
+pub fn parse_entry(entry: &ArchiveEntry, opts: &ParseOptions) -> io::Result<Option<Doc>> {
+    let opts = ParseOptions { follow_includes: false, rewrite_image_paths: false, ..opts.clone() };
+    let doc = parse_doc_from(&entry.path, &entry.contents[..], None, &opts)?;
+    Ok(doc.map(|doc| Doc { archived: Some(entry.contents.clone()), ..doc }))
+}
+
+fn parse_doc_from(path: &Path, input: impl BufRead, mtime: Option<SystemTime>, opts: &ParseOptions) -> io::Result<Option<Doc>> {
+    let mut comment_state = CommentState::Outside;
+
+    let mut doc = Doc {
+        path: path.to_string_lossy().to_string(),
+        revdate: None,
+        revtime: None,
+        archived: None,
+        content: String::new(),
+        has_imagesdir: false,
+        title: None,
+        id: String::from(""),
+        author: None,
+        mtime,
+        attributes: Vec::new(),
+        tags: Vec::new(),
+    };
+
+    let mut doc_imagesdir: Option<String> = opts.force_imagesdir.clone();
+
+    let mut in_header = true;
+    let mut header_started = false;
+    // Whether there's anything besides attribute entries, comments and blank lines.
+    let mut has_content = false;
+
+    // The line after the document title may be an author line.
+    let mut expect_byline = false;
+    let mut byline_author: Option<String> = None;
+
+    let lines = input.split(b'\n');
+
+    for (ln, line) in lines.enumerate() {
+        if let Err(err) = line {
+            return Err(error_with_file_and_line(path, ln, err));
+        }
+        let line = match String::from_utf8(line?) {
+            Ok(line) => line,
+            Err(_) => return Err(error_with_file_and_line(path, ln, error(String::from("Line is not valid UTF-8.")))),
+        };
+
+        let (line, ending) = match line.strip_suffix('\r') {
+            Some(line) if opts.preserve_line_endings => (line, "\r\n"),
+            Some(line) => (line, "\n"),
+            None => (&line[..], "\n"),
+        };
+
+        // A byte-order mark only means something at the very start of the file.
+        let line_without_bom = if ln == 0 { line.strip_prefix(BOM).unwrap_or(line) } else { line };
+        let line_original = if opts.keep_bom { line } else { line_without_bom };
+
+        let line = line_without_bom.trim();
+
+        let comment = comment_state.advance(line);
+
+        let mut imagesdir: Option<String> = None;
+
+        if !comment && in_header {
+            // The header ends at the first blank line after its first line.
+            if line.is_empty() {
+                in_header = !header_started;
+            } else {
+                header_started = true;
+            }
+        }
+
+        if !comment {
+            if line.starts_with("include::") {
+                if !opts.follow_includes {
+                    warning(format!("{}: skipped due to include:: directive (--follow-includes keeps it)", path.display()));
+                    return Ok(None);
+                }
+
+                let mut stack = vec![fs::canonicalize(path)?];
+                if let Some(included) = resolve_include(path, line, &mut stack) {
+                    has_content |= !included.trim().is_empty();
+                    doc.content.push_str(&included);
+                    continue;
+                }
+            }
+
+            if !in_header {
+                if opts.warn_misplaced_revdate && line.starts_with(":revdate:") {
+                    warning(format!("{}:{}: :revdate: outside of the document header is ignored.", path.display(), ln + 1));
+                }
+            } else if attribute_value(line, "revdate").is_some_and(|value| value.starts_with('{')) {
+                // Something like {docdate}, which only Asciidoctor can resolve.
+                warning(format!("{}:{}: revdate is an unresolved attribute reference, treating the document as undated.", path.display(), ln + 1));
+            } else if doc.revdate.is_none() {
+                let revdate = try_parse_date_attribute(line, "revdate");
+                if let Err(err) = revdate {
+                    return Err(error_with_file_and_line(path, ln, err));
+                }
+                if let Some((date, time)) = revdate? {
+                    doc.revdate = Some(date);
+                    doc.revtime = time;
+                }
+            }
+
+            if let Some(name) = attribute_name(line) {
+                doc.attributes.push(name.to_string());
+            } else if !line.is_empty() && !line.starts_with("//") {
+                has_content = true;
+            }
+
+            if doc.author.is_none() {
+                if let Some(author) = attribute_value(line, "author") {
+                    doc.author = Some(author.to_string());
+                }
+            }
+
+            if in_header {
+                if let Some(tags) = attribute_value(line, "tags") {
+                    doc.tags = tags
+                        .split(',')
+                        .map(|tag| tag.trim().to_string())
+                        .filter(|tag| !tag.is_empty())
+                        .collect();
+                }
+            }
+
+            let id = attribute_value(line, "imagesdir");
+            if let Some(id) = id {
+                imagesdir = Some(id.to_string());
+            }
+        }
+
+        let mut pushed = false;
+        if !comment {
+            const IMAGE_PREFIX: &str = "image::";
+
+            if opts.replace_images_with_links && !line.starts_with("//") && line.contains(IMAGE_PREFIX) {
+                let mut line_replaced: Vec<u8> = Vec::new();
+
+                let prefix = IMAGE_PREFIX.as_bytes();
+                let buf = line.as_bytes();
+                let mut i = 0;
+                while i < buf.len() {
+                    if buf[i..].starts_with(prefix) {
+                        i += prefix.len();
+
+                        for c in "link:".bytes() {
+                            line_replaced.push(c);
+                        }
+
+                        if let Some(dir) = doc_imagesdir.as_ref().filter(|dir| !dir.is_empty()) {
+                            for c in dir.bytes() {
+                                line_replaced.push(c);
+                            }
+
+                            if !dir.ends_with('/') {
+                                line_replaced.push(b'/');
+                            }
+                        }
+
+                        continue;
+                    }
+
+                    line_replaced.push(buf[i]);
+                    i += 1;
+                }
+
+                if let Ok(line_replaced) = std::str::from_utf8(&line_replaced) {
+                    doc.content.push_str(line_replaced);
+                    pushed = true;
+                }
+            } else if opts.rewrite_image_paths && !line.starts_with("//") && line.contains("image:") {
+                let dir = image_dir(path, doc_imagesdir.as_deref()).map_err(|err| error_with_file_and_line(path, ln, err))?;
+                if let Some(dir) = dir {
+                    doc.content.push_str(&rewrite_image_targets(line_original, &dir));
+                    pushed = true;
+                }
+            }
+        }
+
+        if !comment {
+            if expect_byline && !line.starts_with("//") {
+                expect_byline = false;
+                if !line.is_empty() && !line.starts_with(':') {
+                    byline_author = byline_author_name(line);
+                }
+            }
+
+            if in_header && doc.title.is_none() && line.starts_with("= ") {
+                doc.title = Some(String::from(&line[2..]));
+                expect_byline = in_header;
+            }
+
+            // We only treat these things before the title as ID
+            if doc.title.is_none() && doc.id.is_empty() {
+                if line.starts_with("[#") && line.ends_with("]") {
+                    doc.id = String::from(&line[2..line.len() - 1]);
+                }
+
+                if line.starts_with("[[") &&  line.ends_with("]]") {
+                    doc.id = String::from(&line[2..line.len() - 2]);
+                }
+            }
+        }
+
+        if imagesdir.is_some() && opts.force_imagesdir.is_some() {
+            continue;
+        }
+
+        if !pushed { doc.content.push_str(line_original); }
+        doc.content.push_str(ending);
+
+        if let Some(dir) = imagesdir {
+            doc_imagesdir = Some(dir.clone());
+
+            doc.has_imagesdir = true;
+
+            // If it's a variable expansion, for example
+            //   {bucket}/{album}
+            // we don't override the imagesdir, because
+            // it may be a URL.
+            // The most reliable way of doing this would be to actually keep track of the
+            // variables in the document and expand them correctly, but that's some work.
+            let maybe_a_variable_expansion = dir
+                .chars()
+                .any(|c| c == '{' || c == '}');
+
+            // Path::starts_with compares whole components, so URLs have to be
+            // recognized on the string.
+            let is_url = dir.starts_with("http://") || dir.starts_with("https://");
+
+            let p = Path::new(&dir);
+            // If we can safely assume this is a local path, we override the imagesdir
+            // with the actual path so that you can get to the image.
+            if !maybe_a_variable_expansion && !is_url && !p.has_root() {
+                let dir = local_imagesdir(&parent_dir(path)?.join(p), opts.imagesdir_base.as_deref())
+                    .map_err(|err| error_with_file_and_line(path, ln, err))?;
+
+                doc.content.push_str(":imagesdir: ");
+                doc.content.push_str(&dir);
+                doc.content.push_str(ending);
+            }
+        }
+    }
+
+    // The comment still swallows the rest of the file, like it does in Asciidoctor.
+    if let Some(delimiter) = comment_state.unterminated() {
+        warning(format!("{}: unterminated {} comment block", path.display(), delimiter));
+    }
+
+    if !has_content {
+        verbose!("skipped (empty): {}", path.display());
+        return Ok(None);
+    }
+
+    // An explicit :author: wins over the author line.
+    if doc.author.is_none() {
+        doc.author = byline_author;
+    }
+
+    Ok(Some(doc))
+}
+
+// Resolves `.` and `..` components without looking at the file system.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+// Path of `target` relative to the directory `base`, both absolute. Paths on
+// different drives have nothing in common and `target` is returned as it is.
+fn relative_path(target: &Path, base: &Path) -> PathBuf {
+    let target = normalize_path(target);
+    let base = normalize_path(base);
+
+    let t: Vec<Component> = target.components().collect();
+    let b: Vec<Component> = base.components().collect();
+    let common = t.iter().zip(&b).take_while(|(t, b)| t == b).count();
+    if common == 0 {
+        return target;
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in common..b.len() {
+        relative.push("..");
+    }
+    for component in &t[common..] {
+        relative.push(component);
+    }
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+    relative
+}
+
+// The imagesdir written for a local image directory: absolute, or relative to
+// --imagesdir-base when it's given.
+fn local_imagesdir(dir: &Path, base: Option<&Path>) -> io::Result<String> {
+    // Documents at the top of a .zip archive have no directory.
+    if dir.as_os_str().is_empty() {
+        return Ok(String::from("."));
+    }
+    let dir = match base {
+        Some(base) => relative_path(dir, base),
+        None => dir.to_path_buf(),
+    };
+    match dir.to_str() {
+        Some(dir) => Ok(forward_slashes(dir)),
+        None => Err(error(String::from("Path is not valid UTF-8."))),
+    }
+}
+
+pub fn parent_dir(path: &Path) -> io::Result<&Path> {
+    path.parent().ok_or_else(|| error_with_file(path, error(String::from("File has no parent directory."))))
+}
+
+pub struct GenerateOptions {
+    pub header: String,
+    pub footer: String,
+    pub source_comments: bool,
+    // Written as `:leveloffset: {+N}` before the documents and undone after them.
+    pub level_offset: i8,
+    // Leave out the leveloffset directives around the documents.
+    pub flat: bool,
+    pub force_imagesdir: Option<String>,
+    pub imagesdir_base: Option<PathBuf>,
+    pub annotate_mtime: bool,
+    pub strip_leading_empty: bool,
+    pub date_anchors: bool,
+    // Write a list of links to the documents before them, and give every
+    // document a [[doc-<slug>]] anchor to link to.
+    pub toc: bool,
+    // Give every document a [[doc-<slug>]] anchor derived from its path, which
+    // the TOC then links to as well.
+    pub anchors: bool,
+    // Put a heading with the year before each run of documents from that year.
+    pub group_by_year: bool,
+    pub line_endings: LineEndings,
+    // Same for the month, nested in the year when both are set.
+    pub group_by_month: bool,
+    // Names of the months in the month headings, January first. Without all
+    // twelve, the English names are used.
+    pub month_names: Vec<String>,
+    // Add the documents to the end of an existing calendar: no header, TOC
+    // or footer, just the documents in their own leveloffset block.
+    pub append: bool,
+    // Compress the output with gzip (needs the 'gzip' feature).
+    pub gzip: bool,
+    // A line written between documents, after the blank line ending the
+    // previous one, e.g. `// ---8<---`.
+    pub separator: Option<String>,
+}
+
+impl GenerateOptions {
+    // Headings of the groups the document belongs to, outermost first.
+    fn groups(&self, doc: &Doc) -> Vec<String> {
+        let mut groups = Vec::new();
+        match doc.revdate {
+            Some(date) => {
+                if self.group_by_year {
+                    groups.push(format!("{:04}", date.year));
+                }
+                if self.group_by_month {
+                    let month = date.month as usize - 1;
+                    let name = match self.month_names.get(month).filter(|_| self.month_names.len() == 12) {
+                        Some(name) => name.clone(),
+                        None => MONTH_NAMES[month][..1].to_uppercase() + &MONTH_NAMES[month][1..],
+                    };
+                    groups.push(format!("{} {:04}", name, date.year));
+                }
+            }
+            None => {
+                if self.group_by_year || self.group_by_month {
+                    groups.push(String::from("Undated"));
+                }
+            }
+        }
+        groups
+    }
+
+    // Level of the outermost group heading: where the document titles would
+    // otherwise end up, but never level 0, which belongs to the calendar title.
+    fn group_level(&self) -> i8 {
+        self.base_offset().max(1)
+    }
+
+    // The extra leveloffset that puts the documents below their group headings.
+    fn group_offset(&self, groups: &[String]) -> i8 {
+        self.group_level() - self.base_offset() + groups.len() as i8
+    }
+
+    // The leveloffset the documents are in outside of any group.
+    fn base_offset(&self) -> i8 {
+        if self.flat { 0 } else { self.level_offset }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum LineEndings {
+    Lf,
+    Crlf,
+    Preserve,
+}
+
+// Rewrites the line endings of everything written through it.
+struct LineEndingWriter<W: Write> {
+    inner: W,
+    endings: LineEndings,
+    // With Lf, a \r at the end of the last write that may start a \r\n.
+    pending_cr: bool,
+    last: u8,
+    // Bytes passed on to inner so far.
+    written: u64,
+}
+
+impl<W: Write> LineEndingWriter<W> {
+    fn new(inner: W, endings: LineEndings) -> Self {
+        LineEndingWriter { inner, endings, pending_cr: false, last: 0, written: 0 }
+    }
+}
+
+impl<W: Write> Write for LineEndingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let LineEndings::Preserve = self.endings {
+            self.inner.write_all(buf)?;
+            self.written += buf.len() as u64;
+            return Ok(buf.len());
+        }
+
+        let mut out = Vec::with_capacity(buf.len() + buf.len() / 32);
+        for &b in buf {
+            match self.endings {
+                LineEndings::Lf => {
+                    if self.pending_cr && b != b'\n' {
+                        out.push(b'\r');
+                    }
+                    self.pending_cr = b == b'\r';
+                    if !self.pending_cr {
+                        out.push(b);
+                    }
+                }
+                LineEndings::Crlf => {
+                    if b == b'\n' && self.last != b'\r' {
+                        out.push(b'\r');
+                    }
+                    out.push(b);
+                }
+                LineEndings::Preserve => out.push(b),
+            }
+            self.last = b;
+        }
+
+        self.inner.write_all(&out)?;
+        self.written += out.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.pending_cr {
+            self.inner.write_all(b"\r")?;
+            self.written += 1;
+            self.pending_cr = false;
+        }
+        self.inner.flush()
+    }
+}
+
+fn strip_leading_empty_lines(mut text: &str) -> &str {
+    while let Some(end) = text.find('\n') {
+        if !text[..end].trim().is_empty() {
+            break;
+        }
+        text = &text[end + 1..];
+    }
+    text
+}
+
+// Cuts the text after its last non-blank line, including that line's
+// line break.
+fn strip_trailing_empty_lines(text: &str) -> &str {
+    let kept = text.trim_end().len();
+    match text[kept..].find('\n') {
+        Some(i) => text[..kept + i].trim_end_matches('\r'),
+        None => text,
+    }
+}
+
+// Formats the time as an ISO 8601 UTC datetime, e.g. 2025-06-01T14:30:00Z.
+pub fn format_timestamp(time: SystemTime) -> String {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+
+    let date = Date::from_ordinal(UNIX_EPOCH_ORDINAL + secs.div_euclid(86400));
+    let rem = secs.rem_euclid(86400);
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        date, rem / 3600, rem % 3600 / 60, rem % 60,
+    )
+}
+
+// Path with forward slashes only, and without the \\?\ prefix canonical
+// paths get on Windows.
+fn forward_slashes(path: &str) -> String {
+    let path = str::replace(path, "\\", "/");
+
+    match path.strip_prefix("//?/") {
+        Some(s) => s.to_string(),
+        None => path,
+    }
+}
+
+// Where a document ended up in the generated output, as byte offsets from
+// the start of it. The range covers the lines written in front of the
+// content (source comment, imagesdir, anchors) but not the blank lines
+// separating it from the next document.
+pub struct DocRange<'a> {
+    pub doc: &'a Doc,
+    pub start: u64,
+    pub end: u64,
+}
+
+pub fn generate<'a, W: Write>(out: W, opts: &GenerateOptions, docs: impl Iterator<Item = &'a Doc>) -> io::Result<usize> {
+    generate_ranges(out, opts, docs).map(|ranges| ranges.len())
+}
+
+// Like generate, but returns where each document was written.
+pub fn generate_ranges<'a, W: Write>(out: W, opts: &GenerateOptions, docs: impl Iterator<Item = &'a Doc>) -> io::Result<Vec<DocRange<'a>>> {
+    let mut buf = LineEndingWriter::new(BufWriter::new(out), opts.line_endings);
+
+    let mut ranges = Vec::new();
+
+    // How many documents with each revdate have been written so far.
+    let mut dates_seen: HashMap<Date, usize> = HashMap::new();
+
+    let docs: Vec<&Doc> = docs.collect();
+    let slugs = if opts.toc || opts.anchors { doc_slugs(&docs, opts.anchors) } else { Vec::new() };
+
+    if !opts.append {
+        buf.write_all(opts.header.as_bytes())?;
+    }
+
+    if opts.toc && !opts.append {
+        buf.write_all("\n\n".as_bytes())?;
+        for (doc, slug) in docs.iter().zip(&slugs) {
+            let title = title_or_filename(doc);
+            match doc.revdate {
+                Some(date) => buf.write_all(format!("* <<doc-{},{} \u{2014} {}>>\n", slug, date, title).as_bytes())?,
+                None => buf.write_all(format!("* <<doc-{},{}>>\n", slug, title).as_bytes())?,
+            }
+        }
+    }
+
+    if opts.flat {
+        buf.write_all("\n\n".as_bytes())?;
+    } else {
+        buf.write_all(format!("\n\n:leveloffset: {:+}\n\n", opts.level_offset).as_bytes())?;
+    }
+
+    // Group headings sit at the level the documents would otherwise have (see
+    // group_level), and the documents are pushed below them. The extra offset
+    // is taken back around each run of headings. With --flat, the documents
+    // stay where they are and only the headings are written.
+    let mut prev_groups: Option<Vec<String>> = None;
+
+    for (i, doc) in docs.iter().enumerate() {
+        if let Some(separator) = opts.separator.as_ref().filter(|_| i > 0) {
+            buf.write_all(format!("{}\n\n", separator).as_bytes())?;
+        }
+
+        let groups = opts.groups(doc);
+        let changed = match prev_groups {
+            Some(ref prev) => (0..groups.len()).find(|&level| prev.get(level) != groups.get(level)),
+            None => if groups.is_empty() { None } else { Some(0) },
+        };
+
+        if let Some(first) = changed {
+            if let Some(prev) = prev_groups.as_ref().filter(|_| !opts.flat) {
+                buf.write_all(format!(":leveloffset: {:+}\n\n", -opts.group_offset(prev)).as_bytes())?;
+            }
+            for (depth, heading) in groups.iter().enumerate().skip(first) {
+                // Written relative to the leveloffset the documents are in.
+                let level = opts.group_level() + depth as i8 - opts.base_offset();
+                buf.write_all(format!("{} {}\n\n", "=".repeat(level as usize + 1), heading).as_bytes())?;
+            }
+            if !opts.flat {
+                buf.write_all(format!(":leveloffset: {:+}\n\n", opts.group_offset(&groups)).as_bytes())?;
+            }
+        }
+        prev_groups = Some(groups);
+
+        let start = buf.written;
+
+        if opts.source_comments {
+            buf.write_all(format!("// source: {}\n", forward_slashes(&doc.path)).as_bytes())?;
+        }
+
+        if opts.annotate_mtime {
+            if let Some(mtime) = doc.mtime {
+                buf.write_all(format!("// mtime: {}\n", format_timestamp(mtime)).as_bytes())?;
+            }
+        }
+
+        if let Some(ref dir) = opts.force_imagesdir {
+            buf.write_all(format!(":imagesdir: {}\n", dir).as_bytes())?;
+        } else if !doc.has_imagesdir {
+            let p = Path::new(&doc.path);
+            let parent = local_imagesdir(parent_dir(p)?, opts.imagesdir_base.as_deref())
+                .map_err(|err| error_with_file(p, err))?;
+
+            buf.write_all(format!(":imagesdir: {}\n", parent).as_bytes())?;
+        }
+
+        if opts.date_anchors {
+            if let Some(date) = doc.revdate {
+                let n = dates_seen.entry(date).or_insert(0);
+                *n += 1;
+
+                if *n == 1 {
+                    buf.write_all(format!("[[date-{}]]\n", date).as_bytes())?;
+                } else {
+                    buf.write_all(format!("[[date-{}-{}]]\n", date, n).as_bytes())?;
+                }
+            }
+        }
+
+        if opts.toc || opts.anchors {
+            buf.write_all(format!("[[doc-{}]]\n", slugs[i]).as_bytes())?;
+        }
+
+        // The separator below makes the one blank line between documents,
+        // whatever the files end with.
+        let mut content = strip_trailing_empty_lines(&doc.content);
+        if opts.strip_leading_empty && ranges.is_empty() {
+            content = strip_leading_empty_lines(content);
+        }
+
+        buf.write_all(content.as_bytes())?;
+        ranges.push(DocRange { doc, start, end: buf.written });
+        buf.write_all("\n\n".as_bytes())?;
+    }
+
+    if let Some(prev) = prev_groups.filter(|prev| !prev.is_empty() && !opts.flat) {
+        buf.write_all(format!(":leveloffset: {:+}\n\n", -opts.group_offset(&prev)).as_bytes())?;
+    }
+
+    if opts.flat {
+        buf.write_all("\n\n".as_bytes())?;
+    } else {
+        buf.write_all(format!("\n\n:leveloffset: {:+}\n\n", -opts.level_offset).as_bytes())?;
+    }
+    if !opts.append {
+        buf.write_all(opts.footer.as_bytes())?;
+    }
+    buf.flush()?;
+
+    Ok(ranges)
+}
+
+// Like generate_ranges, but compresses the output with gzip when asked to.
+// The ranges are still offsets into the uncompressed output.
+pub fn generate_output<'a, W: Write>(out: W, opts: &GenerateOptions, docs: &[&'a Doc]) -> io::Result<Vec<DocRange<'a>>> {
+    if opts.gzip {
+        generate_gzip(out, opts, docs)
+    } else {
+        generate_ranges(out, opts, docs.iter().copied())
+    }
+}
+
+#[cfg(feature = "gzip")]
+fn generate_gzip<'a, W: Write>(out: W, opts: &GenerateOptions, docs: &[&'a Doc]) -> io::Result<Vec<DocRange<'a>>> {
+    let mut encoder = flate2::write::GzEncoder::new(out, flate2::Compression::default());
+    let ranges = generate_ranges(&mut encoder, opts, docs.iter().copied())?;
+    encoder.finish()?;
+    Ok(ranges)
+}
+
+#[cfg(not(feature = "gzip"))]
+fn generate_gzip<'a, W: Write>(_out: W, _opts: &GenerateOptions, _docs: &[&'a Doc]) -> io::Result<Vec<DocRange<'a>>> {
+    Err(error(String::from("This build has no gzip support (rebuild with --features gzip).")))
+}
+
+// One line per document: path, start and end offset, revdate (empty if
+// there is none), separated by tabs.
+pub fn write_manifest(path: &Path, ranges: &[DocRange]) -> io::Result<()> {
+    write_atomically(path, |file| {
+        let mut out = BufWriter::new(file);
+        for range in ranges {
+            let revdate = range.doc.revdate.map(|date| date.to_string()).unwrap_or_default();
+            writeln!(out, "{}\t{}\t{}\t{}", forward_slashes(&range.doc.path), range.start, range.end, revdate)?;
+        }
+        out.flush()
+    })
+    .map_err(|err| error_with_file(path, err))
+}
+
+pub fn write_calendar<'a>(path: &Path, opts: &GenerateOptions, docs: &[&'a Doc], fail_if_changed: bool) -> io::Result<(Vec<DocRange<'a>>, bool)> {
+    let generated = if fail_if_changed {
+        let mut output: Vec<u8> = Vec::new();
+        generate_output(&mut output, opts, docs).and_then(|ranges| {
+            Ok((ranges, write_if_changed(path, &output)?))
+        })
+    } else if opts.append {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|file| generate_output(file, opts, docs))
+            .map(|ranges| (ranges, true))
+    } else {
+        write_atomically(path, |file| generate_output(file, opts, docs)).map(|ranges| (ranges, true))
+    };
+
+    generated.map_err(|err| error_with_file(path, err))
+}
+
+#[derive(Clone, Copy)]
+pub enum Bucket {
+    Weekly,
+    Monthly,
+    Quarterly,
+    Yearly,
+}
+
+impl Bucket {
+    // Ordinal of the first day of the bucket containing the date.
+    fn start(self, date: Date) -> i64 {
+        let first = |month| Date { year: date.year, month, day: 1 }.to_ordinal();
+        match self {
+            Bucket::Weekly => {
+                let ordinal = date.to_ordinal();
+                // 0000-03-01 was a Wednesday; weeks start on Monday.
+                ordinal - (ordinal + 2).rem_euclid(7)
+            }
+            Bucket::Monthly => first(date.month),
+            Bucket::Quarterly => first((date.month - 1) / 3 * 3 + 1),
+            Bucket::Yearly => first(1),
+        }
+    }
+
+    fn next(self, start: i64) -> i64 {
+        let Date { year, month, .. } = Date::from_ordinal(start);
+        let first = |year, month| Date { year, month, day: 1 }.to_ordinal();
+        match self {
+            Bucket::Weekly => start + 7,
+            Bucket::Monthly if month == 12 => first(year + 1, 1),
+            Bucket::Monthly => first(year, month + 1),
+            Bucket::Quarterly if month >= 10 => first(year + 1, 1),
+            Bucket::Quarterly => first(year, month + 3),
+            Bucket::Yearly => first(year + 1, 1),
+        }
+    }
+
+    // Name of the bucket starting on that day, e.g. 2025-W23, 2025-06, 2025-Q2 or 2025.
+    fn key(self, start: i64) -> String {
+        let Date { year, month, .. } = Date::from_ordinal(start);
+        match self {
+            Bucket::Weekly => {
+                // ISO weeks belong to the year their Thursday falls in.
+                let thursday = start + 3;
+                let iso_year = Date::from_ordinal(thursday).year;
+                let week = (thursday - Date { year: iso_year, month: 1, day: 1 }.to_ordinal()) / 7 + 1;
+                format!("{:04}-W{:02}", iso_year, week)
+            }
+            Bucket::Monthly => format!("{:04}-{:02}", year, month),
+            Bucket::Quarterly => format!("{:04}-Q{}", year, (month - 1) / 3 + 1),
+            Bucket::Yearly => format!("{:04}", year),
+        }
+    }
+}
+
+pub struct SplitOptions {
+    pub bucket: Bucket,
+    pub emit_empty: bool,
+    // Prepended to the file names, e.g. calendar- for calendar-2025.adoc.
+    pub prefix: &'static str,
+    // Also write an index.adoc that includes the calendars, newest first.
+    pub index: bool,
+}
+
+// Writes one calendar per bucket into the directory, plus one for the undated
+// documents if there are any.
+pub fn write_split(dir: &Path, split: &SplitOptions, opts: &GenerateOptions, docs: &[&Doc], fail_if_changed: bool) -> io::Result<(usize, bool)> {
+    fs::create_dir_all(dir).map_err(|err| error_with_file(dir, err))?;
+
+    let SplitOptions { bucket, emit_empty, prefix, .. } = *split;
+
+    let mut buckets: BTreeMap<i64, Vec<&Doc>> = BTreeMap::new();
+    let mut undated: Vec<&Doc> = Vec::new();
+    for doc in docs {
+        match doc.revdate {
+            Some(date) => buckets.entry(bucket.start(date)).or_default().push(doc),
+            None => undated.push(doc),
+        }
+    }
+
+    if emit_empty {
+        if let (Some(&first), Some(&last)) = (buckets.keys().next(), buckets.keys().next_back()) {
+            let mut start = first;
+            while start < last {
+                start = bucket.next(start);
+                buckets.entry(start).or_default();
+            }
+        }
+    }
+
+    let mut count = 0;
+    let mut changed = false;
+    let mut names = Vec::new();
+
+    for (start, docs) in &buckets {
+        let name = format!("{}{}.adoc", prefix, bucket.key(*start));
+        let (ranges, ch) = write_calendar(&dir.join(&name), opts, docs, fail_if_changed)?;
+        count += ranges.len();
+        changed |= ch;
+        names.push(name);
+    }
+    names.reverse();
+
+    if !undated.is_empty() {
+        let name = format!("{}undated.adoc", prefix);
+        let (ranges, ch) = write_calendar(&dir.join(&name), opts, &undated, fail_if_changed)?;
+        count += ranges.len();
+        changed |= ch;
+        names.push(name);
+    }
+
+    if split.index {
+        let mut index = LineEndingWriter::new(Vec::new(), opts.line_endings);
+        for name in &names {
+            index.write_all(format!("include::{}[]\n\n", name).as_bytes())?;
+        }
+
+        let path = dir.join("index.adoc");
+        changed |= write_if_changed(&path, &index.inner).map_err(|err| error_with_file(&path, err))?;
+    }
+
+    Ok((count, changed))
+}
+
+// Writes the contents to the file unless it already holds exactly these
+// contents, in which case the file (and its mtime) is left alone.
+// Returns whether the file was written.
+fn write_if_changed(path: &Path, contents: &[u8]) -> io::Result<bool> {
+    if let Ok(existing) = fs::read(path) {
+        if existing == contents {
+            return Ok(false);
+        }
+    }
+
+    write_atomically(path, |mut file| file.write_all(contents))?;
+    Ok(true)
+}
+
+// Writes the file through a sibling <name>.tmp file that replaces it only once
+// everything has been written, so a failed write leaves the old file alone.
+fn write_atomically<T>(path: &Path, write: impl FnOnce(File) -> io::Result<T>) -> io::Result<T> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+
+    let result = File::create(&tmp).and_then(write).and_then(|value| {
+        match fs::rename(&tmp, path) {
+            Ok(()) => {},
+            // Only possible if the directory is a mount point of its own, but
+            // a copy still beats failing.
+            Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+                fs::copy(&tmp, path)?;
+                fs::remove_file(&tmp)?;
+            }
+            Err(err) => return Err(err),
+        }
+        Ok(value)
+    });
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+
+    slug
+}
+
+// Slugs of the documents, derived from their file names, or with `from_path`
+// from their paths relative to the current directory (without the extension).
+// Documents that would end up with the same slug get a numeric suffix, in
+// order: foo, foo-2, foo-3...
+fn doc_slugs(docs: &[&Doc], from_path: bool) -> Vec<String> {
+    let mut used: HashSet<String> = HashSet::new();
+    let mut slugs = Vec::with_capacity(docs.len());
+
+    let cwd = env::current_dir().ok();
+
+    for doc in docs {
+        let path = Path::new(&doc.path);
+        let stem = match (from_path, &cwd) {
+            (true, Some(cwd)) => relative_path(&path.with_extension(""), cwd).to_string_lossy().to_string(),
+            (true, None) => path.with_extension("").to_string_lossy().to_string(),
+            (false, _) => path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default(),
+        };
+
+        let mut base = slugify(&stem);
+        if base.is_empty() {
+            base = String::from("doc");
+        }
+
+        let mut slug = base.clone();
+        let mut n = 2;
+        while used.contains(&slug) {
+            slug = format!("{}-{}", base, n);
+            n += 1;
+        }
+
+        used.insert(slug.clone());
+        slugs.push(slug);
+    }
+
+    slugs
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_json(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// The documents as a JSON array of objects with their path, revdate, title,
+// author and whether they set an imagesdir.
+pub fn write_json_array<'a, W: Write>(out: W, docs: impl Iterator<Item = &'a Doc>) -> io::Result<usize> {
+    let mut buf = BufWriter::new(out);
+
+    let mut count = 0;
+    buf.write_all("[".as_bytes())?;
+    for (i, doc) in docs.enumerate() {
+        let revdate = match doc.revdate {
+            Some(date) => format!("\"{}\"", date),
+            None => String::from("null"),
+        };
+
+        let title = match doc.title {
+            Some(ref title) => format!("\"{}\"", escape_json(title)),
+            None => String::from("null"),
+        };
+
+        let revtime = match doc.revtime {
+            Some(time) => format!("\"{}\"", time),
+            None => String::from("null"),
+        };
+
+        let author = match doc.author {
+            Some(ref author) => format!("\"{}\"", escape_json(author)),
+            None => String::from("null"),
+        };
+
+        let separator = if i == 0 { "" } else { "," };
+        buf.write_all(format!(
+            "{}\n  {{\"path\": \"{}\", \"revdate\": {}, \"revtime\": {}, \"title\": {}, \"author\": {}, \"has_imagesdir\": {}}}",
+            separator, escape_json(&doc.path), revdate, revtime, title, author, doc.has_imagesdir,
+        ).as_bytes())?;
+        count += 1;
+    }
+    buf.write_all("\n]\n".as_bytes())?;
+
+    buf.flush()?;
+    Ok(count)
+}
+
+// Like write_calendar, but writes the JSON array instead of the calendar.
+pub fn write_json(path: &Path, docs: &[&Doc], fail_if_changed: bool) -> io::Result<(usize, bool)> {
+    let written = if fail_if_changed {
+        let mut output: Vec<u8> = Vec::new();
+        write_json_array(&mut output, docs.iter().copied()).and_then(|count| {
+            Ok((count, write_if_changed(path, &output)?))
+        })
+    } else {
+        write_atomically(path, |file| write_json_array(file, docs.iter().copied())).map(|count| (count, true))
+    };
+
+    written.map_err(|err| error_with_file(path, err))
+}
+
+// The links point to the [[doc-<slug>]] anchors that generate() writes, so
+// from_path has to match the one it derives the slugs with.
+pub fn generate_html_index(path: &Path, base_url: &str, docs: &[&Doc], from_path: bool) -> io::Result<()> {
+    let slugs = doc_slugs(docs, from_path);
+
+    write_atomically(path, |file| {
+        let mut buf = BufWriter::new(file);
+
+        buf.write_all("<ul>\n".as_bytes())?;
+        for (doc, slug) in docs.iter().zip(slugs) {
+            let date = match doc.revdate {
+                Some(date) => format!("{} ", date),
+                None => String::new(),
+            };
+
+            let href = escape_html(&format!("{}#doc-{}", base_url, slug));
+            let title = escape_html(title_or_filename(doc));
+            buf.write_all(format!("<li>{}<a href=\"{}\">{}</a></li>\n", date, href, title).as_bytes())?;
+        }
+        buf.write_all("</ul>\n".as_bytes())?;
+
+        buf.flush()
+    })
+    .map_err(|err| error_with_file(path, err))
+}
+
+// Groups of documents whose files have identical contents, each group and
+// the groups in order of their paths.
+pub fn duplicate_groups(docs: &[Doc]) -> io::Result<Vec<Vec<&str>>> {
+    // Group by a hash of the file contents first, then compare the contents
+    // themselves so that a hash collision can't produce a false positive.
+    let mut by_hash: HashMap<u64, Vec<(&Doc, Vec<u8>)>> = HashMap::new();
+    for doc in docs {
+        let bytes = read_doc_bytes(doc)?;
+
+        let mut hasher = DefaultHasher::new();
+        bytes.hash(&mut hasher);
+        by_hash.entry(hasher.finish()).or_default().push((doc, bytes));
+    }
+
+    let mut groups: Vec<Vec<&str>> = Vec::new();
+    for (_, mut candidates) in by_hash {
+        while let Some((doc, bytes)) = candidates.pop() {
+            let mut group = vec![doc.path.as_str()];
+            candidates.retain(|(other, other_bytes)| {
+                if *other_bytes == bytes {
+                    group.push(&other.path);
+                    false
+                } else {
+                    true
+                }
+            });
+
+            if group.len() > 1 {
+                group.sort();
+                groups.push(group);
+            }
+        }
+    }
+    groups.sort();
+
+    Ok(groups)
+}
+
+// Changelog-style directories keep their entries in descending date order
+// by file name. Returns every entry that breaks that order, after the one
+// it should be older than.
+pub fn out_of_order(docs: &[Doc]) -> Vec<(&Doc, &Doc)> {
+    let mut found = Vec::new();
+    let mut dirs: BTreeMap<&Path, Vec<&Doc>> = BTreeMap::new();
+    for doc in docs {
+        if doc.revdate.is_none() {
+            continue;
+        }
+        let dir = Path::new(&doc.path).parent().unwrap_or(Path::new(""));
+        dirs.entry(dir).or_default().push(doc);
+    }
+
+    for (_, mut entries) in dirs {
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        for pair in entries.windows(2) {
+            let (prev, next) = (pair[0], pair[1]);
+            let (Some(prev_date), Some(next_date)) = (prev.revdate, next.revdate) else {
+                continue;
+            };
+            if prev_date < next_date {
+                found.push((prev, next));
+            }
+        }
+    }
+
+    found
+}
+
+const KNOWN_ATTRIBUTES: &[&str] = &["revdate", "imagesdir", "author", "title", "tags"];
+
+// How often each attribute the tool doesn't recognize is set, most used first.
+pub fn unknown_attributes(docs: &[Doc]) -> Vec<(&str, usize)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for doc in docs {
+        for name in &doc.attributes {
+            if !KNOWN_ATTRIBUTES.contains(&name.as_str()) {
+                *counts.entry(name).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut counts: Vec<(&str, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    counts
+}
+
+// The bytes of the document's source, from the archive it was read from
+// or else from disk.
+fn read_doc_bytes(doc: &Doc) -> io::Result<Vec<u8>> {
+    if let Some(bytes) = &doc.archived {
+        return Ok(bytes.clone());
+    }
+    let path = Path::new(&doc.path);
+    fs::read(path).map_err(|err| error_with_file(path, err))
+}
+
+// 64-bit FNV-1a. Unlike DefaultHasher, it gives the same result on every
+// build, so the hashes can be stored between runs.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// Compares the documents against the hashes stored in the cache file by the
+// previous run, rewrites the cache, and returns the paths of the documents
+// that are new or changed. The cache is a plain text file with one path=hash
+// line per document.
+pub fn changed_since_last_run<'a>(cache_path: &Path, docs: &'a [Doc]) -> io::Result<Vec<&'a str>> {
+    let mut cached: HashMap<String, String> = HashMap::new();
+    match fs::read_to_string(cache_path) {
+        Ok(cache) => {
+            for line in cache.lines() {
+                if let Some((path, hash)) = line.rsplit_once('=') {
+                    cached.insert(path.to_string(), hash.to_string());
+                }
+            }
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(error_with_file(cache_path, err)),
+    }
+
+    let mut hashes: BTreeMap<&str, String> = BTreeMap::new();
+    for doc in docs {
+        let bytes = read_doc_bytes(doc)?;
+        hashes.insert(&doc.path, format!("{:016x}", fnv1a(&bytes)));
+    }
+
+    let changed: Vec<&str> = hashes
+        .iter()
+        .filter(|(path, hash)| cached.get(**path) != Some(*hash))
+        .map(|(path, _)| *path)
+        .collect();
+
+    let mut cache = String::new();
+    for (path, hash) in hashes {
+        cache.push_str(&format!("{}={}\n", path, hash));
+    }
+    fs::write(cache_path, cache).map_err(|err| error_with_file(cache_path, err))?;
+
+    Ok(changed)
+}
+
+// Drops all but the newest document in each directory. Of documents with
+// the same revdate, the one with the first path stays. Undated documents
+// are only kept in directories without a dated one.
+pub fn keep_newest_per_dir(docs: &mut Vec<Doc>) {
+    let mut newest: HashMap<PathBuf, (Date, String)> = HashMap::new();
+    for doc in docs.iter() {
+        let Some(date) = doc.revdate else {
+            continue;
+        };
+        let dir = Path::new(&doc.path).parent().unwrap_or(Path::new("")).to_path_buf();
+        let candidate = (date, doc.path.clone());
+        match newest.get_mut(&dir) {
+            Some(best) => {
+                if date > best.0 || (date == best.0 && doc.path < best.1) {
+                    *best = candidate;
+                }
+            }
+            None => {
+                newest.insert(dir, candidate);
+            }
+        }
+    }
+
+    docs.retain(|doc| {
+        let dir = Path::new(&doc.path).parent().unwrap_or(Path::new(""));
+        let keep = match newest.get(dir) {
+            Some((_, path)) => *path == doc.path,
+            None => true,
+        };
+        if !keep {
+            verbose!("skipped (not the newest in its directory): {}", doc.path);
+        }
+        keep
+    });
+}
+
+// Pairs of documents that share a revdate and time, with the paths of each
+// pair in order. As when sorting, a revdate without a time counts as 00:00.
+pub fn duplicate_revdates<'a>(docs: &[&'a Doc]) -> Vec<(Date, Option<Time>, &'a str, &'a str)> {
+    let mut dated: Vec<(Date, Time, &str, Option<Time>)> = docs
+        .iter()
+        .filter_map(|doc| doc.revdate.map(|date| (date, doc.revtime.unwrap_or_default(), doc.path.as_str(), doc.revtime)))
+        .collect();
+    dated.sort();
+
+    dated
+        .windows(2)
+        .filter(|pair| (pair[0].0, pair[0].1) == (pair[1].0, pair[1].1))
+        .map(|pair| (pair[0].0, pair[0].3.or(pair[1].3), pair[0].2, pair[1].2))
+        .collect()
+}
+
+// The runs of days without a document between the first and the last dated
+// document: first and last day of each, and the number of days.
+pub fn date_gaps(docs: &[&Doc]) -> Vec<(Date, Date, i64)> {
+    let mut dates: Vec<i64> = docs.iter().filter_map(|doc| doc.revdate).map(Date::to_ordinal).collect();
+    dates.sort();
+    dates.dedup();
+
+    dates
+        .windows(2)
+        .filter(|pair| pair[1] - pair[0] > 1)
+        .map(|pair| (Date::from_ordinal(pair[0] + 1), Date::from_ordinal(pair[1] - 1), pair[1] - pair[0] - 1))
+        .collect()
+}
+
+// The revdates of the first and the last dated document and the days between
+// them, both included. None without dated documents.
+pub fn date_span(docs: &[&Doc]) -> Option<(Date, Date, i64)> {
+    let dated = docs.iter().filter_map(|doc| doc.revdate);
+    let (first, last) = (dated.clone().min()?, dated.max()?);
+    Some((first, last, last.to_ordinal() - first.to_ordinal() + 1))
+}
+
+// The authors of the documents with the number of documents by each, most
+// prolific first.
+pub fn author_counts<'a>(docs: impl Iterator<Item = &'a Doc>) -> Vec<(&'a str, usize)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for doc in docs {
+        if let Some(ref author) = doc.author {
+            *counts.entry(author).or_insert(0) += 1;
+        }
+    }
+
+    let mut counts: Vec<(&str, usize)> = counts.into_iter().collect();
+    // Most prolific authors first, ties broken alphabetically.
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    counts
+}
+
+#[derive(Default)]
+pub struct PathFilter {
+    #[cfg(feature = "regex")]
+    include: Vec<regex::Regex>,
+    #[cfg(feature = "regex")]
+    exclude: Vec<regex::Regex>,
+}
+
+impl PathFilter {
+    #[cfg(feature = "regex")]
+    pub fn new(include: &[String], exclude: &[String]) -> io::Result<Self> {
+        let compile = |patterns: &[String]| -> io::Result<Vec<regex::Regex>> {
+            patterns
+                .iter()
+                .map(|p| regex::Regex::new(p).map_err(|e| error(format!("Invalid path regex '{}': {}", p, e))))
+                .collect()
+        };
+
+        Ok(PathFilter {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+        })
+    }
+
+    #[cfg(not(feature = "regex"))]
+    pub fn new(include: &[String], exclude: &[String]) -> io::Result<Self> {
+        if !include.is_empty() || !exclude.is_empty() {
+            return Err(error(String::from("This build has no regex support (rebuild with --features regex).")));
+        }
+        Ok(PathFilter::default())
+    }
+
+    #[cfg(feature = "regex")]
+    fn accepts(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy();
+        if !self.include.is_empty() && !self.include.iter().any(|r| r.is_match(&path)) {
+            return false;
+        }
+        !self.exclude.iter().any(|r| r.is_match(&path))
+    }
+
+    #[cfg(not(feature = "regex"))]
+    fn accepts(&self, _path: &Path) -> bool {
+        true
+    }
+}
+
+// Matches a name against a pattern where `*` stands for any run of characters
+// and `?` for a single character.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+
+    let mut pi = 0;
+    let mut ti = 0;
+    // Position of the last `*` seen and of the text it was tried against.
+    let mut star: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            // Let the `*` swallow one more character and try again.
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+
+    while pi < p.len() && p[pi] == '*' {
+        pi += 1;
+    }
+    pi == p.len()
+}
+
+const IGNORE_FILE: &str = ".calendarignore";
+
+// Reads the patterns of a .calendarignore file: one per line, blank lines and
+// lines starting with # are skipped. A trailing / makes a pattern match
+// directories only.
+fn read_ignore_file(path: &Path) -> io::Result<Vec<String>> {
+    let text = fs::read_to_string(path).map_err(|err| error_with_file(path, err))?;
+    Ok(text
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+fn is_ignored(patterns: &[String], name: &str, is_dir: bool) -> bool {
+    patterns.iter().any(|pattern| {
+        match pattern.strip_suffix('/') {
+            Some(dir_pattern) => is_dir && glob_match(dir_pattern, name),
+            None => glob_match(pattern, name),
+        }
+    })
+}
+
+pub struct TraverseOptions {
+    pub filter: PathFilter,
+    // How many directories deep to descend below a source directory (unlimited if None).
+    pub max_depth: Option<usize>,
+    // Lowercase extensions (without the dot) of the files to collect.
+    pub extensions: Vec<String>,
+    // --exclude patterns. Matched against the file name, or against the whole
+    // path (with forward slashes) when the pattern contains a /.
+    pub exclude: Vec<String>,
+}
+
+impl TraverseOptions {
+    fn excludes(&self, path: &Path) -> bool {
+        let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        let full = forward_slashes(&path.to_string_lossy());
+
+        self.exclude.iter().any(|pattern| {
+            if pattern.contains('/') {
+                glob_match(pattern, &full)
+            } else {
+                glob_match(pattern, &name)
+            }
+        })
+    }
+}
+
+pub const DEFAULT_EXTENSIONS: &[&str] = &["adoc", "asciidoc", "asc", "ad"];
+
+#[derive(Default)]
+pub struct FoundFiles {
+    // Absolute, but not canonical: symlinks are kept so that the imagesdir of
+    // a document is the directory it was found in.
+    pub files: Vec<PathBuf>,
+    // Documents read from .zip archives given as source.
+    pub entries: Vec<ArchiveEntry>,
+    // Canonical paths of the files and directories seen so far. A file reached
+    // twice through symlinks is only collected once (the first time), and a
+    // symlink cycle is only followed once.
+    seen: HashSet<PathBuf>,
+    // Regular files that were looked at, including the rejected ones.
+    pub scanned: usize,
+    // With --progress, when the count of scanned files was last printed.
+    pub progress: Option<Instant>,
+}
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+impl FoundFiles {
+    // Returns false if the file or directory has been seen before.
+    fn visit(&mut self, path: &Path) -> io::Result<bool> {
+        Ok(self.seen.insert(fs::canonicalize(path)?))
+    }
+
+    fn count_scanned(&mut self) {
+        self.scanned += 1;
+
+        if let Some(last) = self.progress {
+            if last.elapsed() >= PROGRESS_INTERVAL {
+                eprint!("\rscanned {} files...", self.scanned);
+                self.progress = Some(Instant::now());
+            }
+        }
+    }
+
+    // Ends the progress line with the final count.
+    pub fn finish_progress(&self) {
+        if self.progress.is_some() {
+            eprintln!("\rscanned {} files.   ", self.scanned);
+        }
+    }
+}
+
+pub fn get_adoc_files(path: &Path, opts: &TraverseOptions, ignore: &[String], depth: usize, found: &mut FoundFiles) -> io::Result<()> {
+    if path.is_dir() {
+        if !found.visit(path)? {
+            verbose!("skipped (already seen): {}", path.display());
+            return Ok(());
+        }
+
+        // Ignore patterns apply to the directory they're in and everything below it.
+        let mut patterns = ignore.to_vec();
+        let ignore_file = path.join(IGNORE_FILE);
+        if ignore_file.is_file() {
+            patterns.extend(read_ignore_file(&ignore_file)?);
+        }
+
+        // read_dir returns the entries in whatever order the file system keeps
+        // them, so sort them to visit files in the same order everywhere.
+        let mut entries = fs::read_dir(path)?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let path = entry.path();
+
+            let is_dir = path.is_dir();
+            if is_dir && opts.max_depth.is_some_and(|max| depth >= max) {
+                continue;
+            }
+
+            let name = entry.file_name();
+            if is_ignored(&patterns, &name.to_string_lossy(), is_dir) {
+                continue;
+            }
+
+            get_adoc_files(&path, opts, &patterns, depth + 1, found)?;
+        }
+    } else if depth == 0 && is_zip(path) {
+        File::open(path)
+            .and_then(|file| read_zip(file, opts, found))
+            .map_err(|err| error_with_file(path, err))?;
+    } else if path.is_file() {
+        found.count_scanned();
+
+        let ext = path.extension();
+        if ext.is_none() {
+            verbose!("skipped (wrong extension): {}", path.display());
+            return Ok(());
+        } else if let Some(ext) = ext {
+            let ext = ext.to_string_lossy().to_lowercase();
+            if !opts.extensions.contains(&ext) {
+                verbose!("skipped (wrong extension): {}", path.display());
+                return Ok(());
+            }
+        }
+        if opts.excludes(path) {
+            verbose!("skipped (excluded): {}", path.display());
+            return Ok(());
+        }
+
+        let path = path::absolute(path)?;
+        if !opts.filter.accepts(&path) {
+            verbose!("skipped (path filter): {}", path.display());
+        } else if !found.visit(&path)? {
+            verbose!("skipped (already seen): {}", path.display());
+        } else {
+            found.files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+pub struct ArchiveEntry {
+    pub path: PathBuf,
+    pub contents: Vec<u8>,
+}
+
+// Whether the path is a .zip archive to read the documents from.
+pub fn is_zip(path: &Path) -> bool {
+    path.is_file() && path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+// Like get_adoc_files, but reads the documents out of a .zip archive, in
+// the order of their paths. There are no ignore files in archives.
+#[cfg(feature = "zip")]
+pub fn read_zip(archive: impl io::Read + io::Seek, opts: &TraverseOptions, found: &mut FoundFiles) -> io::Result<()> {
+    let mut archive = zip::ZipArchive::new(archive)?;
+
+    let mut names: Vec<String> = archive.file_names().map(String::from).collect();
+    names.sort();
+
+    for name in names {
+        let mut file = archive.by_name(&name)?;
+        if !file.is_file() {
+            continue;
+        }
+        // Entries with names like ../a.adoc don't get a path at all.
+        let Some(path) = file.enclosed_name() else {
+            verbose!("skipped (unsafe path): {}", name);
+            continue;
+        };
+        found.count_scanned();
+
+        let depth = path.components().count() - 1;
+        let ext = path.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default();
+        if opts.max_depth.is_some_and(|max| depth > max) {
+            continue;
+        } else if !opts.extensions.contains(&ext) {
+            verbose!("skipped (wrong extension): {}", name);
+            continue;
+        } else if opts.excludes(&path) {
+            verbose!("skipped (excluded): {}", name);
+            continue;
+        } else if !opts.filter.accepts(&path) {
+            verbose!("skipped (path filter): {}", name);
+            continue;
+        }
+
+        let mut contents = Vec::new();
+        io::Read::read_to_end(&mut file, &mut contents)?;
+        found.entries.push(ArchiveEntry { path, contents });
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "zip"))]
+pub fn read_zip(_archive: impl io::Read + io::Seek, _opts: &TraverseOptions, _found: &mut FoundFiles) -> io::Result<()> {
+    Err(error(String::from("This build has no zip support (rebuild with --features zip).")))
+}
+
+// Reads newline-separated file paths, e.g. from `git diff --name-only`.
+// The files are taken as they are: no extension check, no ignore files.
+pub fn read_listed_files(input: impl BufRead, found: &mut FoundFiles) -> io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            continue;
+        }
+
+        let path = Path::new(line);
+        if !path.is_file() {
+            return Err(error(format!("Listed file '{}' does not exist or is not a file.", line)));
+        }
+
+        found.count_scanned();
+        if found.visit(path)? {
+            found.files.push(path::absolute(path)?);
+        }
+    }
+
+    Ok(())
+}
+
+pub struct CollectOptions {
+    pub traverse: TraverseOptions,
+    pub parse: ParseOptions,
+}
+
+// Collects and parses the documents under the directory, in the order they
+// were found. Empty documents and the ones skipped because of include::
+// directives are left out.
+pub fn collect(src: &Path, opts: &CollectOptions) -> io::Result<Vec<Doc>> {
+    let mut found = FoundFiles::default();
+    get_adoc_files(src, &opts.traverse, &[], 0, &mut found)?;
+
+    let mut docs = Vec::new();
+    for path in found.files {
+        if let Some(doc) = parse_doc(&path, &opts.parse)? {
+            docs.push(doc);
+        }
+    }
+    for entry in &found.entries {
+        if let Some(doc) = parse_entry(entry, &opts.parse)? {
+            docs.push(doc);
+        }
+    }
+
+    Ok(docs)
+}
+
+pub fn title_or_filename(doc: &Doc) -> &str {
+    if let Some(ref title) = doc.title {
+        return title;
+    }
+
+    Path::new(&doc.path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: u16, month: u8, day: u8) -> Date {
+        Date { year, month, day }
+    }
+
+    #[test]
+    fn dates_order_by_year_month_day() {
+        assert!(date(2024, 1, 31) < date(2024, 2, 1));
+        assert!(date(2023, 12, 31) < date(2024, 1, 1));
+        assert!(date(2024, 6, 1) <= date(2024, 6, 1) && date(2024, 6, 1) >= date(2024, 6, 1));
+
+        // The revdate sort compares in reverse for newest first and relies on
+        // undated documents (None) comparing less than any date to end up last.
+        let mut revdates = vec![Some(date(2024, 1, 1)), None, Some(date(2025, 1, 1)), Some(date(2023, 1, 1))];
+        revdates.sort_by(|a, b| b.cmp(a));
+        assert_eq!(revdates, [Some(date(2025, 1, 1)), Some(date(2024, 1, 1)), Some(date(2023, 1, 1)), None]);
+    }
+
+    #[test]
+    fn known_ordinals() {
+        assert_eq!(date(1970, 1, 1).to_ordinal(), UNIX_EPOCH_ORDINAL);
+        assert_eq!(date(0, 3, 1).to_ordinal(), 0);
+        assert_eq!(date(2000, 2, 29).to_ordinal(), 730484);
+        assert_eq!(date(2000, 3, 1).to_ordinal(), 730485);
+        assert_eq!(date(2025, 6, 1).to_ordinal(), 739708);
+        assert_eq!(Date::from_ordinal(UNIX_EPOCH_ORDINAL), date(1970, 1, 1));
+        assert_eq!(Date::from_ordinal(739708), date(2025, 6, 1));
+    }
+
+    #[test]
+    fn ordinals_round_trip() {
+        // Spans the leap years 2000 and 2004 and the common years between.
+        let first = date(1999, 1, 1).to_ordinal();
+        let mut prev = Date::from_ordinal(first - 1);
+        for ordinal in first..date(2005, 1, 1).to_ordinal() {
+            let date = Date::from_ordinal(ordinal);
+            assert_eq!(date.to_ordinal(), ordinal);
+            assert!(date > prev);
+            assert!(date.day >= 1 && date.day <= days_in_month(date.year, date.month));
+            prev = date;
+        }
+    }
+
+    #[test]
+    fn century_leap_years() {
+        // 1900 is divisible by 100 but not by 400, so it has no Feb 29.
+        assert_eq!(date(1900, 2, 28).add_days(1), date(1900, 3, 1));
+        assert_eq!(date(2000, 2, 28).add_days(1), date(2000, 2, 29));
+        assert_eq!(date(2100, 2, 28).add_days(1), date(2100, 3, 1));
+        assert_eq!(date(1900, 3, 1).to_ordinal() - date(1899, 3, 1).to_ordinal(), 365);
+        assert_eq!(date(2000, 3, 1).to_ordinal() - date(1999, 3, 1).to_ordinal(), 366);
+    }
+
+    #[test]
+    fn path_without_parent_is_an_error() {
+        let err = parent_dir(Path::new("/")).unwrap_err();
+        assert_eq!(err.to_string(), "/: File has no parent directory.");
+        assert!(parent_dir(Path::new("")).is_err());
+        assert_eq!(parent_dir(Path::new("a.adoc")).unwrap(), Path::new(""));
+        assert_eq!(parent_dir(Path::new("/posts/a.adoc")).unwrap(), Path::new("/posts"));
+    }
+
+    #[test]
+    fn day_of_month_is_validated() {
+        assert_eq!(try_parse_date("2024-04-30").unwrap(), date(2024, 4, 30));
+        assert!(try_parse_date("2024-04-31").is_err());
+        assert_eq!(try_parse_date("2024-11-30").unwrap(), date(2024, 11, 30));
+        assert!(try_parse_date("2024-11-31").is_err());
+        assert_eq!(try_parse_date("2024-01-31").unwrap(), date(2024, 1, 31));
+        assert_eq!(try_parse_date("2024-12-31").unwrap(), date(2024, 12, 31));
+        assert!(try_parse_date("2024-12-32").is_err());
+        assert!(try_parse_date("2024-06-00").is_err());
+
+        assert!(try_parse_date("1900-02-29").is_err());
+        assert_eq!(try_parse_date("2000-02-29").unwrap(), date(2000, 2, 29));
+        assert_eq!(try_parse_date("2024-02-29").unwrap(), date(2024, 2, 29));
+        assert!(try_parse_date("2023-02-29").is_err());
+        assert!(try_parse_date("2024-02-30").is_err());
+    }
+}
@@ -1,43 +1,60 @@
-use std::{env, u16};
-use std::process::ExitCode;
-use std::io::{self, BufRead, BufReader, Error, ErrorKind, BufWriter, Write};
-use std::fs::{self, File};
-use std::path::{Path, PathBuf};
+use std::env;
+use std::process::{Command, ExitCode};
+use std::io::{self, IsTerminal};
+use std::fs;
+use std::path::{self, Path, PathBuf};
 use std::cmp::Ordering;
-use std::collections::HashSet;
-use std::time::{Instant};
-
-#[derive(Clone, Copy)]
-#[derive(Debug)]
-struct Date {
-    year: u16,
-    month: u8,
-    day: u8,
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+
+use calendar_fast::{
+    author_counts, changed_since_last_run, date_gaps, date_span, duplicate_groups, duplicate_revdates, error,
+    error_with_file, error_with_file_and_line, format_timestamp, generate_html_index, generate_output,
+    get_adoc_files, is_zip, keep_newest_per_dir, out_of_order, parent_dir, parse_doc, parse_entry, parse_since,
+    read_listed_files, set_verbosity, title_or_filename, try_parse_date_bound, unknown_attributes, verbosity,
+    warning, write_calendar, write_json, write_json_array, write_manifest, write_split,
+    Bucket, Date, Doc, FoundFiles, GenerateOptions, LineEndings, ParseOptions, PathFilter, SplitOptions,
+    TraverseOptions, Verbosity, DEFAULT_EXTENSIONS, MONTH_NAMES,
+};
+
+// Set when the calendar (or the --count) is written to stdout, so that
+// everything else has to go to stderr.
+static INFO_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+// Like println!, but silenced by --quiet and moved out of the way when stdout
+// carries the calendar.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if verbosity() == Verbosity::Quiet {
+        } else if INFO_TO_STDERR.load(AtomicOrdering::Relaxed) {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
 }
 
-impl Date {
-    fn ge(&self, other: &Self) -> bool {
-        if self.year > other.year { return true; }
-        if self.year == other.year && self.month > other.month { return true; }
-        if self.year == other.year && self.month == other.month && self.day >= other.day { return true; }
-        false
-    }
-
-    fn le(&self, other: &Self) -> bool {
-        if self.year < other.year { return true; }
-        if self.year == other.year && self.month < other.month { return true; }
-        if self.year == other.year && self.month == other.month && self.day <= other.day { return true; }
-        false
-    }
+// Output of the report options. Like info!, but --quiet doesn't silence it,
+// since the report was asked for.
+macro_rules! report {
+    ($($arg:tt)*) => {
+        if INFO_TO_STDERR.load(AtomicOrdering::Relaxed) {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
 }
 
-struct Doc {
-    path: String,
-    revdate: Option<Date>,
-    content: String,
-    title: String,
-    id: String,
-    has_imagesdir: bool,
+// Per-file diagnostics for --verbose. Always goes to stderr.
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if verbosity() == Verbosity::Verbose {
+            eprintln!($($arg)*);
+        }
+    };
 }
 
 fn usage() {
@@ -46,12 +63,133 @@ fn usage() {
   -h, --help                  Print the help message.
   -v, --version               Print the version number and the build date.
   -o             PATH         Output file.
+  --config       PATH         Read default options from PATH (default: calendar.toml or calendar.conf
+                              in the first source directory, if there is one).
+  --stdout                    Write the calendar to stdout instead of a file. Everything else goes to stderr.
+  --split-dir    DIR          Write one calendar per date bucket into DIR instead of a single output file.
+  --bucket       weekly|monthly|quarterly|yearly
+                              Date bucket used by --split-dir (default: yearly).
+  --emit-empty-groups         With --split-dir, also write calendars for empty buckets between the first and last one.
+  --split-by-year             Write one calendar per year into the --output-dir directory, named calendar-YYYY.adoc
+                              (calendar-undated.adoc for undated documents), and an index.adoc including them, newest first.
+  --output-dir   DIR          Directory for --split-by-year.
+  --dry-run                   Print the documents that would be included (revdate and path) to stderr
+                              instead of writing anything.
+  --count                     Print the number of documents that would be included to stdout instead of writing anything.
+  --watch                     Keep running and rebuild whenever a source file, the header or the footer changes.
+  --gzip                      Compress the output with gzip (default output file: calendar.adoc.gz).
+                              Needs the 'gzip' feature.
+  --append                    Add the documents to the end of the output file instead of replacing it.
+                              The header and the footer are left out, so the footer is up to you.
+  --allow-output-in-source    Write the output even if it's inside a source directory, where the next run reads it.
+  --fail-if-changed           Only write the output file if its contents change, and fail if they did.
+  --line-endings lf|crlf|preserve
+                              Line endings of the output (default: lf). With preserve, every line keeps
+                              the ending it had in its source file.
   --header       PATH         Header file.
+  --title        TEXT         Title of the default header (default: Calendar). Can't be used with --header.
+  --header-from-first-doc     Use the title of the first document as the title of the default header, with
+                              its author and revdate below it (at --header-level 0). Can't be used with --header or --title.
   --footer       PATH         Footer file.
-  --start-date   YYYY-MM-DD   Start date (inclusive).
-  --end-date     YYYY-MM-DD   End date (inclusive).
+  --separator    LINE         Also write LINE between documents, e.g. '// ---8<---'.
+  --header-level 0|1|2        Heading level of the default title (documents are nested one level below it).
+  --level-offset N            Level offset applied to the documents, may be zero or negative
+                              (default: one below the --header-level title, or 1 with --header).
+  --flat                      Keep the documents at their own heading levels (no leveloffset directives).
+  --start-date   YYYY-MM-DD   Start date (inclusive). YYYY or YYYY-MM start on the first day of the year or month.
+  --since        Nd|Nw|Nm     Start date N days, weeks or months before today.
+  --end-date     YYYY-MM-DD   End date (inclusive). YYYY or YYYY-MM end on the last day of the year or month.
+  --between      START..END   Both date bounds at once, in the same forms. Either one may be left out.
+  --limit        N            Only include the first N documents (after filtering, in the chosen order).
+  --newest-per-dir            Only keep the document with the newest revdate in each directory.
+                              Undated documents are kept only in directories without a dated one.
+  --include-undated           Keep documents without a :revdate: (at the end) even with date bounds.
+  --exclude-undated           Drop documents without a :revdate: even without date bounds.
+                              By default they are kept unless --start-date, --end-date or --since is given.
+  --tag          NAME         Only include documents with this tag in their :tags: (repeatable, any of them).
   --imglink                   Replace images with links (will not work correctly on variable expansions).
+  --rewrite-image-paths       Make the relative targets of image:: and image: macros absolute paths into the
+                              document's directory (or the local imagesdir it sets).
+  --force-imagesdir VALUE     Use this imagesdir for every document, dropping the documents' own ones.
+  --imagesdir-base DIR        Write the imagesdirs of local image directories relative to DIR instead of absolute,
+                              e.g. the directory of the output file.
+  --annotate-mtime            Write each document's file modification time in a comment before it.
+  --group-by-year             Put a heading with the year before the documents of each year
+                              (and an Undated one before undated documents).
+  --group-by-month            Same for months, e.g. June 2025. Nested in the years with --group-by-year.
+  --month-names  NAMES        Comma-separated names of the twelve months for --group-by-month.
+  --source-comments           Write a // source: PATH comment before each document.
+  --strip-leading-empty       Remove blank lines from the start of the first document.
+  --date-anchors              Put a [[date-YYYY-MM-DD]] anchor before each dated document (-2, -3... for repeated dates).
+  --toc                       Put a list of links to the documents (revdate and title) before them.
+  --anchors                   Put a [[doc-SLUG]] anchor before each document, SLUG being its path relative to the
+                              current directory in lowercase, with other characters turned into hyphens (-2, -3...
+                              for repeated slugs). With --toc, the links point to these anchors.
+  --strict-revdate-position   Warn about :revdate: entries after the document header (they are always ignored).
+  --follow-includes           Splice included files into the documents. Without it, documents with
+                              include:: directives are skipped.
+  --keep-bom                  Keep byte-order marks in the document contents instead of stripping them.
+  --strict                    Treat duplicate revdates as an error instead of a warning.
+  --strict-paths              Fail if a source path is not valid UTF-8.
+  --require-revdate           Fail if any document has no :revdate:, listing them all.
+  --keep-going                Warn about documents that fail to parse and leave them out instead of stopping.
+                              The calendar is still written, but the exit code is 7.
+  --progress                  Show how many files have been scanned so far while collecting them
+                              (only when stderr is a terminal).
+  --quiet                     Only print errors (and the output of report options like --list-authors).
+  --verbose                   Print to stderr what happened to each file, and a summary of the counts.
+  --ext          EXT          Collect files with this extension (repeatable, case-insensitive).
+                              Replaces the default set: adoc, asciidoc, asc, ad.
+  --exclude      GLOB         Skip files whose name matches (repeatable, * and ? wildcards).
+                              Patterns containing a / are matched against the whole path instead.
+  --max-depth    N            Don't descend more than N directories below a source directory.
+  --include-path-regex REGEX  Only collect files whose full path matches (repeatable, needs the 'regex' feature).
+  --exclude-path-regex REGEX  Skip files whose full path matches (repeatable, needs the 'regex' feature).
+                              Every collected file is matched against every pattern, which adds up on huge trees.
+  --list-authors              Print the authors of the included documents with their document counts and exit.
+  --check-monotonic           Warn when documents in a directory are not in descending date order by file name.
+  --report-unknown-attributes Print how often each attribute the tool doesn't recognize is used.
+  --only-changed PATH         Print the documents that changed since the last run, using PATH as the hash cache.
+                              The calendar is still generated from all documents.
+  --summary                   After writing the output, print the dates it spans and the number of documents to stderr.
+  --report-gaps               Print the runs of days without any included document.
+  --report-duplicates         Print groups of source files with identical contents and exit.
+  --json         PATH         Also write a JSON array describing every collected document, in sorted order.
+  --html-index   PATH         Also write an HTML list of links to the included documents. Implies --anchors
+                              unless --toc is given.
+  --base-url     URL          URL the HTML index links point to (default: none, links are just anchors).
+  --manifest     PATH         Also write where each document ended up in the output, one line per document:
+                              path, start and end byte offset, revdate, separated by tabs.
+                              With --gzip, the offsets are into the uncompressed output.
+  --format       adoc|json    What to write: the calendar (default), or a JSON array describing the included
+                              documents, like --json does (default output file: calendar.json).
   --order-by     revdate|title|id
+  --ascending                 Put the oldest documents first when ordering by revdate.
+  --descending                Put the newest documents first when ordering by revdate (default).
+  --reverse-within-day        Order documents with the same revdate by path descending instead of ascending.
+  --title-from-filename       Use the file name as the title of documents that have none (for ordering and indexes).
+  --filename-title-delimiter STR
+                              With --title-from-filename, only use the part of the file name after STR.
+  --missing-title first|last|filename
+                              Where documents without a title go when ordering by title (default: last).
+
+A source path of - reads a list of files from stdin, one per line. Listed files are used
+as they are, regardless of their extension, --max-depth, path filters and .calendarignore.
+A source path ending in .zip reads the documents from the archive (needs the 'zip' feature).
+Their paths, and so their imagesdirs, are the ones inside the archive. --follow-includes and
+--rewrite-image-paths don't work with them.
+
+Options that take a value also accept it as --option=value.
+
+Exit codes: 0 success, 2 invalid options, 3 missing or unreadable sources (or header/footer),
+4 invalid documents (including --require-revdate and --strict failures), 5 errors writing
+the output or reports, 6 --fail-if-changed found the output out of date, 7 --keep-going left
+out documents that failed to parse, 1 anything else.
+A config file has one key = value per line and # comments. The keys are out, header, footer,
+start-date, end-date, group-by-year, group-by-month (true or false) and month-names.
+Paths are relative to the config file, and options on the command line override it.
+A .calendarignore file lists name patterns (* and ? wildcards, trailing / for directories)
+to skip in its directory and everything below it.
 ");
 }
 
@@ -59,316 +197,373 @@ fn version() {
    eprintln!("calendar-fast 0.1.0, built on 2026-06-23.");
 }
 
-fn error(text: String) -> Error {
-    Error::new(ErrorKind::Other, text)
-}
-
-fn error_with_file(path: &Path, err: Error) -> Error {
-    Error::new(ErrorKind::Other, format!("{}: {}", path.display(), err))
-}
-
-fn error_with_file_and_line(path: &Path, line: usize, err: Error) -> Error {
-    Error::new(ErrorKind::Other, format!("{}:{}: {}", path.display(), line + 1, err))
+#[derive(Clone, Copy, PartialEq)]
+enum ConfigValue {
+    Path,
+    Text,
+    Flag,
 }
 
-fn try_parse_date(date: &str) -> io::Result<Date> {
-        let len = 4 + 1 + 2 + 1 + 2;
-        let mut ok = date.len() == len;
-
-        let mut year = 0u16;
-        let mut month = 0u8;
-        let mut day = 0u8;
-
-        if ok {
-            let date = date.as_bytes();
-            ok = date[4] == b'-' && date[7] == b'-';
+// Keys of a config file: the option each one stands for, the kind of value it
+// takes, and the command-line options that take precedence over it.
+const CONFIG_KEYS: &[(&str, &str, ConfigValue, &[&str])] = &[
+    ("out", "-o", ConfigValue::Path, &["-o", "--stdout", "--split-dir", "--split-by-year"]),
+    ("header", "--header", ConfigValue::Path, &["--header", "--title", "--header-from-first-doc"]),
+    ("footer", "--footer", ConfigValue::Path, &["--footer"]),
+    ("start-date", "--start-date", ConfigValue::Text, &["--start-date", "--since", "--between"]),
+    ("end-date", "--end-date", ConfigValue::Text, &["--end-date", "--between"]),
+    ("group-by-year", "--group-by-year", ConfigValue::Flag, &["--group-by-year"]),
+    ("group-by-month", "--group-by-month", ConfigValue::Flag, &["--group-by-month"]),
+    ("month-names", "--month-names", ConfigValue::Text, &["--month-names"]),
+];
+
+// Looked for in the first source directory when there's no --config.
+const CONFIG_FILE_NAMES: &[&str] = &["calendar.toml", "calendar.conf"];
+
+// Reads `key = value` lines (and # comments) into the options they stand for.
+// Relative paths are taken relative to the config file. Options given on the
+// command line win, so the ones they override are left out.
+fn read_config(path: &Path, given: &HashSet<String>) -> io::Result<Vec<String>> {
+    let contents = fs::read_to_string(path).map_err(|err| error_with_file(path, err))?;
+    let dir = parent_dir(&path::absolute(path)?)?.to_path_buf();
+
+    let mut args = Vec::new();
+    for (ln, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
         }
 
-        if ok {
-            year = date[0..=3].parse().unwrap_or_else(|_| { ok = false; 0 });
-            month = date[5..=6].parse().unwrap_or_else(|_| { ok = false; 0 });
-            day = date[8..=9].parse().unwrap_or_else(|_| { ok = false; 0 });
-
-            ok = year > 0 && month >= 1 && month <= 12 && day >= 1 && day <= 31;
-        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(error_with_file_and_line(path, ln, error(String::from("Expected 'key = value'."))));
+        };
+        let key = key.trim();
+        let value = value.trim();
+        // Quoted values keep calendar.toml valid TOML.
+        let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
 
-        if !ok {
-            return Err(error(format!("Could not parse date '{}'", date)));
+        let Some(&(_, option, kind, overridden_by)) = CONFIG_KEYS.iter().find(|(name, ..)| *name == key) else {
+            return Err(error_with_file_and_line(path, ln, error(format!("Unknown key '{}'.", key))));
+        };
+        if overridden_by.iter().any(|name| given.contains(*name)) {
+            continue;
         }
 
-        Ok(Date {year, month, day})
-}
-
-fn try_parse_date_with_prefix(line: &str, prefix: &'static str) -> io::Result<Option<Date>> {
-    if let Some(date) = line.strip_prefix(prefix) {
-        match try_parse_date(date) {
-            Ok(d) => Ok(Some(d)),
-            Err(e) => Err(e),
+        match kind {
+            ConfigValue::Flag => match value {
+                "true" => args.push(option.to_string()),
+                "false" => {},
+                _ => return Err(error_with_file_and_line(path, ln, error(format!("{} is either true or false.", key)))),
+            },
+            ConfigValue::Path => args.push(format!("{}={}", option, dir.join(value).display())),
+            ConfigValue::Text => args.push(format!("{}={}", option, value)),
         }
-    } else {
-        Ok(None)
     }
-}
 
-static BOM: &'static str = unsafe { std::str::from_utf8_unchecked(&[0xEF, 0xBB, 0xBF]) };
+    Ok(args)
+}
 
-fn parse_doc(path: &Path, replace_images_with_links: bool) -> io::Result<Option<Doc>> {
-    let file = File::open(path);
-    if let Err(err) = file {
-        return Err(error_with_file(path, err));
+// The config file given with --config, or else the first one found in the
+// first source directory.
+fn find_config(config_path: Option<&str>, src_dirs: &[String]) -> Option<PathBuf> {
+    if let Some(path) = config_path {
+        return Some(PathBuf::from(path));
     }
-    let file = file?;
-    let lines = BufReader::new(file).lines();
-
-    let mut cmt_block = false;
-    let mut cmt_section = false;
-    let mut cmt_section_block = false;
-
-    let mut doc = Doc {
-        path: path.to_string_lossy().to_string(),
-        revdate: None,
-        content: String::new(),
-        has_imagesdir: false,
-        title: String::from(""),
-        id: String::from(""),
-    };
-
-    let mut doc_imagesdir: Option<String> = None;
-
-    for (ln, line) in lines.enumerate() {
-        if let Err(err) = line {
-            return Err(error_with_file_and_line(path, ln, err));
-        }
-        let line = line?;
-
-        let mut line_original = &line[..];
-        if let Some(nb) = line_original.strip_prefix(BOM) {
-            line_original = nb;
-        }
-
-        let line = line_original.trim();
-
-        if line == "////" {
-            cmt_block = !cmt_block;
-        } else if line == "[comment]" {
-            cmt_section = true;
-        } else if cmt_section {
-            if line == "--" {
-                if !cmt_section_block {
-                    cmt_section_block = true;
-                } else {
-                    cmt_section_block = false;
-                    cmt_section = false;
-                }
-            } else if line == "" {
-                if !cmt_section_block {
-                    cmt_section = false
-                }
-            }
-        }
-
-        let mut imagesdir: Option<String> = None;
 
-        let comment = cmt_block || cmt_section;
-        if !comment {
-            if line.starts_with("include::") { return Ok(None); }
+    let dir = src_dirs.iter().find(|dir| *dir != "-")?;
+    CONFIG_FILE_NAMES.iter().map(|name| Path::new(dir).join(name)).find(|path| path.is_file())
+}
 
-            if let None = doc.revdate {
-                let revdate = try_parse_date_with_prefix(line, ":revdate: ");
-                if let Err(err) = revdate {
-                    return Err(error_with_file_and_line(path, ln, err));
-                }
-                if let Some(date) = revdate? {
-                    doc.revdate = Some(date);
-                }
-            }
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
 
-            let id = line.strip_prefix(":imagesdir: ");
-            if let Some(id) = id {
-                imagesdir = Some(id.to_string());
-            }
+// Modification times of everything a build reads, to tell when to rebuild.
+fn watch_snapshot(src_dirs: &[String], extra_files: &[PathBuf], opts: &TraverseOptions) -> HashMap<PathBuf, Option<SystemTime>> {
+    let mut found = FoundFiles::default();
+    for dir in src_dirs {
+        if let Err(err) = get_adoc_files(Path::new(dir), opts, &[], 0, &mut found) {
+            eprintln!("Error: {err}");
         }
+    }
 
-        let mut pushed = false;
-        if !comment {
-            const IMAGE_PREFIX: &str = "image::";
-
-            if replace_images_with_links && !line.starts_with("//") && line.contains(IMAGE_PREFIX) {
-                let mut line_replaced: Vec<u8> = Vec::new();
-
-                let prefix = IMAGE_PREFIX.as_bytes();
-                let buf = line.as_bytes();
-                let mut i = 0;
-                while i < buf.len() {
-                    if buf[i..].starts_with(prefix) {
-                        i += prefix.len();
-
-                        for c in "link:".bytes() {
-                            line_replaced.push(c);
-                        }
-
-                        if let Some(ref dir) = doc_imagesdir {
-                            for c in dir.bytes() {
-                                line_replaced.push(c);
-                            }
-
-                            let db = dir.as_bytes();
-                            if db[db.len() - 1] != b'/' {
-                                line_replaced.push(b'/');
-                            }
-                        }
-
-                        continue;
-                    }
-
-                    line_replaced.push(buf[i]);
-                    i += 1;
-                }
+    // The documents in a .zip archive change with the archive.
+    let archives = src_dirs.iter().map(PathBuf::from).filter(|path| is_zip(path));
+
+    found.files
+        .into_iter()
+        .chain(archives)
+        .chain(extra_files.iter().cloned())
+        .map(|path| {
+            let mtime = fs::metadata(&path).and_then(|m| m.modified()).ok();
+            (path, mtime)
+        })
+        .collect()
+}
 
-                if let Ok(line_replaced) = std::str::from_utf8(&line_replaced) {
-                    doc.content.push_str(line_replaced);
-                    pushed = true;
-                }
-            }
+// Runs this program again without --watch whenever a source file, the header
+// or the footer changes. Each build runs in its own process, so a failing
+// build only prints its errors and the watch goes on.
+fn watch(src_dirs: &[String], extra_files: &[PathBuf], opts: &TraverseOptions, out: &str) -> ExitCode {
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            return ExitCode::from(1);
         }
-
-        if !comment {
-            if doc.title == "" && line.starts_with("= ") {
-                doc.title = String::from(&line[2..]);
-            }
-
-            // We only treat these things before the title as ID
-            if doc.title == "" && doc.id == "" {
-                if line.starts_with("[#") && line.ends_with("]") {
-                    doc.id = String::from(&line[2..line.len() - 1]);
-                }
-
-                if line.starts_with("[[") &&  line.ends_with("]]") {
-                    doc.id = String::from(&line[2..line.len() - 2]);
+    };
+    let args: Vec<String> = env::args().skip(1).filter(|arg| arg != "--watch").collect();
+
+    let mut last: Option<HashMap<PathBuf, Option<SystemTime>>> = None;
+    loop {
+        let snapshot = watch_snapshot(src_dirs, extra_files, opts);
+        if last.as_ref() != Some(&snapshot) {
+            match Command::new(&exe).args(&args).status() {
+                Ok(status) if status.success() => {
+                    eprintln!("rebuilt {} at {}", out, format_timestamp(SystemTime::now()));
                 }
+                // The build has printed its errors already.
+                Ok(_) => {},
+                Err(err) => eprintln!("Error: {err}"),
             }
+            last = Some(snapshot);
         }
 
-        if !pushed { doc.content.push_str(&line_original); }
-        doc.content.push_str("\n");
-
-        if let Some(dir) = imagesdir {
-            doc_imagesdir = Some(dir.clone());
-
-            doc.has_imagesdir = true;
-
-            // If it's a variable expansion, for example
-            //   {bucket}/{album}
-            // we don't override the imagesdir, because
-            // it may be a URL.
-            // The most reliable way of doing this would be to actually keep track of the
-            // variables in the document and expand them correctly, but that's some work.
-            let maybe_a_variable_expansion = dir
-                .chars()
-                .any(|c| c == '{' || c == '}');
-
-            let p = Path::new(&dir);
-            // If we can safely assume this is a local path, we override the imagesdir
-            // with the actual path so that you can get to the image.
-            // TODO: This is not a very good way to determine if the path is a URL.
-            // HACK: unwrap
-            if !maybe_a_variable_expansion && !p.has_root() &&
-               !p.starts_with("http://") && !p.starts_with("https://")
-            {
-                doc.content.push_str(":imagesdir: ");
-                doc.content.push_str(&str::replace(path.parent().unwrap().join(p).to_str().unwrap(), "\\", "/"));
-                doc.content.push_str("\n");
-            }
-        }
+        thread::sleep(WATCH_INTERVAL);
     }
-
-    Ok(Some(doc))
 }
 
-fn generate<'a>(path: &str, header: &str, footer: &str, docs: impl Iterator<Item = &'a Doc>) -> io::Result<usize> {
-    let file = File::create(path)?;
-    let mut buf = BufWriter::new(file);
-
-    let mut count_generated = 0;
-
-    buf.write(header.as_bytes())?;
-    buf.write("\n\n:leveloffset: +1\n\n".as_bytes())?;
-
-    for doc in docs {
-        if !doc.has_imagesdir {
-            let p = Path::new(&doc.path);
-            // TODO: unwrap
-            let parent = p.parent().unwrap().to_str().unwrap();
-            let mut parent = str::replace(parent, "\\", "/");
+enum Format {
+    Adoc,
+    Json,
+}
 
-            if let Some(s) = parent.strip_prefix("//?/") {
-                parent = s.to_string();
-            }
+enum OrderBy {
+    Revdate,
+    Title,
+    ID,
+}
 
-            buf.write(format!(":imagesdir: {}\n", parent).as_bytes())?;
+// Absolute path of something that may not exist yet, with the directory it
+// would be in resolved.
+fn canonical_target(path: &Path) -> io::Result<PathBuf> {
+    match fs::canonicalize(path) {
+        Ok(path) => Ok(path),
+        Err(err) => {
+            let name = path.file_name().ok_or(err)?;
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            Ok(fs::canonicalize(dir)?.join(name))
         }
+    }
+}
 
-        buf.write(doc.content.as_bytes())?;
-        buf.write("\n\n".as_bytes())?;
-
-        count_generated += 1;
+// The source directory the output would be written into, if any. Output the
+// next run wouldn't pick up, because of its extension, doesn't count.
+fn source_dir_containing(out: &Path, is_dir: bool, src_dirs: &[String], extensions: &[String]) -> Option<String> {
+    let ext = if is_dir {
+        String::from("adoc")
+    } else {
+        out.extension()?.to_string_lossy().to_lowercase()
+    };
+    if !extensions.contains(&ext) {
+        return None;
     }
 
-    buf.write("\n\n:leveloffset: -1\n\n".as_bytes())?;
-    buf.write(footer.as_bytes())?;
+    let out = canonical_target(out).ok()?;
+    src_dirs
+        .iter()
+        .filter(|dir| *dir != "-")
+        .find(|dir| fs::canonicalize(dir).is_ok_and(|dir| out.starts_with(dir)))
+        .cloned()
+}
 
-    Ok(count_generated)
+enum MissingTitle {
+    First,
+    Last,
+    Filename,
 }
 
-fn get_adoc_files(path: &Path, files: &mut HashSet<PathBuf>) -> io::Result<()> {
-    if path.is_dir() {
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let path = entry.path();
-            get_adoc_files(&path, files)?;
-        }
-    } else if path.is_file() {
-        let ext = path.extension();
-        if ext.is_none() {
-            return Ok(());
-        } else if let Some(ext) = ext {
-            if ext.to_str() != Some("adoc") {
-                return Ok(());
-            }
+// Turns a file name like 2025-06-01__my-first_post.adoc into "my first post",
+// dropping everything up to the delimiter when one is given.
+fn title_from_filename(path: &str, delimiter: Option<&str>) -> String {
+    let stem = Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut title = stem.as_str();
+    if let Some(delimiter) = delimiter {
+        if let Some((_, rest)) = title.split_once(delimiter) {
+            title = rest;
         }
-        files.insert(fs::canonicalize(path).unwrap());
     }
 
-    Ok(())
+    title
+        .split(['-', '_'])
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
 }
 
-enum OrderBy {
-    Revdate,
-    Title,
-    ID,
+// Exit codes, so that scripts can tell the failures apart. 1 is left for
+// anything unexpected.
+const EXIT_USAGE: u8 = 2;
+const EXIT_SOURCE: u8 = 3;
+const EXIT_PARSE: u8 = 4;
+const EXIT_OUTPUT: u8 = 5;
+const EXIT_CHANGED: u8 = 6;
+const EXIT_SKIPPED: u8 = 7;
+
+// Value of an option, either given inline (--name=value) or as the next argument.
+// An empty inline value counts as missing.
+fn next_value(inline: &mut Option<String>, args: &mut VecDeque<String>) -> Option<String> {
+    match inline.take() {
+        Some(value) if value.is_empty() => None,
+        Some(value) => Some(value),
+        None => args.pop_front(),
+    }
 }
 
 fn main() -> ExitCode {
     let perf_total = Instant::now();
 
-    let mut args = env::args();
-    args.next().unwrap();
+    let mut args: VecDeque<String> = env::args().collect();
+    // Skip the program name.
+    args.pop_front();
 
     let mut src_dirs: Vec<String> = Vec::new();
 
-    let mut out_path = String::from("calendar.adoc");
+    let mut out_path: Option<String> = None;
+    let mut to_stdout = false;
+    let mut fail_if_changed = false;
+    let mut append = false;
+    let mut gzip = false;
+    let mut split_dir: Option<String> = None;
+    let mut output_dir: Option<String> = None;
+    let mut split_by_year = false;
+    let mut bucket: Option<Bucket> = None;
+    let mut emit_empty_groups = false;
     let mut header_path: Option<String> = None;
+    let mut title: Option<String> = None;
+    let mut header_from_first_doc = false;
     let mut footer_path: Option<String> = None;
+    let mut separator: Option<String> = None;
+    let mut header_level = 0u8;
+    let mut level_offset: Option<i8> = None;
+    let mut flat = false;
 
     let mut start_date = Date { year: 0, month: 0, day: 0 };
     let mut end_date = Date { year: u16::MAX, month: u8::MAX, day: u8::MAX };
     let mut date_bounds_specified = false;
+    let mut start_date_specified = false;
+    let mut since: Option<String> = None;
+    // Whether to keep undated documents. By default they are kept unless
+    // there are date bounds.
+    let mut include_undated: Option<bool> = None;
 
     let mut replace_images_with_links = false;
+    let mut rewrite_image_paths = false;
+    let mut keep_bom = false;
+    let mut strict_revdate_position = false;
+    let mut force_imagesdir: Option<String> = None;
+    let mut imagesdir_base: Option<PathBuf> = None;
+    let mut annotate_mtime = false;
+    let mut strip_leading_empty = false;
+    let mut date_anchors = false;
+    let mut toc = false;
+    let mut anchors = false;
+    let mut require_revdate = false;
+    let mut keep_going = false;
+    let mut dry_run = false;
+    let mut allow_output_in_source = false;
+    let mut progress = false;
+    let mut count_only = false;
+    let mut source_comments = false;
+    let mut group_by_year = false;
+    let mut gaps = false;
+    let mut summary = false;
+    let mut follow_includes = false;
+    let mut watch_sources = false;
+    let mut line_endings = LineEndings::Lf;
+    let mut group_by_month = false;
+    let mut month_names: Vec<String> = MONTH_NAMES
+        .iter()
+        .map(|name| name[..1].to_uppercase() + &name[1..])
+        .collect();
+
+    let mut strict_paths = false;
+    let mut quiet = false;
+    let mut verbose = false;
+    let mut strict = false;
+
+    let mut authors_only = false;
+
+    let mut duplicates_only = false;
+    let mut changed_cache: Option<String> = None;
+    let mut check_monotonic_dates = false;
+    let mut report_unknown_attributes = false;
+
+    let mut json_path: Option<String> = None;
+    let mut html_index_path: Option<String> = None;
+    let mut manifest_path: Option<String> = None;
+    let mut base_url = String::new();
+
+    let mut max_depth: Option<usize> = None;
+    let mut limit: Option<usize> = None;
+    let mut newest_per_dir = false;
+    let mut extensions: Vec<String> = Vec::new();
+    let mut tags: Vec<String> = Vec::new();
+    let mut exclude: Vec<String> = Vec::new();
+
+    let mut include_path_regex: Vec<String> = Vec::new();
+    let mut exclude_path_regex: Vec<String> = Vec::new();
 
     let mut order_by = OrderBy::Revdate;
+    let mut format = Format::Adoc;
+    let mut missing_title = MissingTitle::Last;
+    let mut ascending = false;
+    let mut reverse_within_day = false;
+    let mut derive_titles = false;
+    let mut filename_title_delimiter: Option<String> = None;
+
+    let mut config_path: Option<String> = None;
+    let mut config_file: Option<PathBuf> = None;
+    let mut config_read = false;
+    // Options given on the command line, which take precedence over the config file.
+    let mut given: HashSet<String> = HashSet::new();
+
+    loop {
+        let Some(arg) = args.pop_front() else {
+            // The config file is read once the command line is done, so that
+            // its options go through the same parsing.
+            if config_read {
+                break;
+            }
+            config_read = true;
+
+            config_file = find_config(config_path.as_deref(), &src_dirs);
+            if let Some(path) = &config_file {
+                match read_config(path, &given) {
+                    Ok(config_args) => args.extend(config_args),
+                    Err(err) => {
+                        eprintln!("Error: {err}");
+                        return ExitCode::from(EXIT_USAGE);
+                    }
+                }
+            }
+            continue;
+        };
+
+        // Options also accept their value in the --name=value form.
+        let (arg, mut value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") || name == "-o" => (name.to_string(), Some(value.to_string())),
+            _ => (arg, None),
+        };
+        if !config_read {
+            given.insert(arg.clone());
+        }
 
-    while let Some(arg) = args.next() {
         match arg.as_str() {
             "-h" | "--help" => {
                 usage();
@@ -378,62 +573,536 @@ fn main() -> ExitCode {
                 version();
                 return ExitCode::SUCCESS;
             }
+            "--config" => {
+                match next_value(&mut value, &mut args) {
+                    Some(path) => config_path = Some(path),
+                    None => {
+                        eprintln!("Error: You typed --config, but didn't specify what the file is afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    },
+                }
+            }
             "--header" => {
-                match args.next() {
+                match next_value(&mut value, &mut args) {
                     Some(path) => header_path = Some(path),
                     None => {
                         eprintln!("Error: You typed --header, but didn't specify what the file is afterwards.");
-                        return ExitCode::from(1);
+                        return ExitCode::from(EXIT_USAGE);
                     },
                 }
             }
+            "--title" => {
+                match next_value(&mut value, &mut args) {
+                    Some(text) => title = Some(text),
+                    None => {
+                        eprintln!("Error: You typed --title, but didn't specify the title afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    },
+                }
+            }
+            "--header-from-first-doc" => {
+                header_from_first_doc = true;
+            }
             "--footer" => {
-                match args.next() {
+                match next_value(&mut value, &mut args) {
                     Some(path) => footer_path = Some(path),
                     None => {
                         eprintln!("Error: You typed --footer, but didn't specify what the file is afterwards.");
-                        return ExitCode::from(1);
+                        return ExitCode::from(EXIT_USAGE);
                     },
                 }
             }
+            "--separator" => {
+                match next_value(&mut value, &mut args) {
+                    Some(line) => separator = Some(line),
+                    None => {
+                        eprintln!("Error: You typed --separator, but didn't specify the line afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    },
+                }
+            }
+            "--header-level" => {
+                header_level = match next_value(&mut value, &mut args) {
+                    Some(level) => {
+                        match level.as_str() {
+                            "0" => 0,
+                            "1" => 1,
+                            "2" => 2,
+                            &_ => {
+                                eprintln!("Error: --header-level is either 0, 1, or 2.");
+                                return ExitCode::from(EXIT_USAGE);
+                            }
+                        }
+                    }
+                    None => {
+                        eprintln!("Error: You typed --header-level, but didn't specify the level afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    }
+                }
+            }
+            "--level-offset" => {
+                level_offset = match next_value(&mut value, &mut args) {
+                    Some(offset) => {
+                        match offset.parse::<i8>() {
+                            Ok(offset) if (-5..=5).contains(&offset) => Some(offset),
+                            _ => {
+                                eprintln!("Error: --level-offset expects a number from -5 to 5, got '{}'.", offset);
+                                return ExitCode::from(EXIT_USAGE);
+                            }
+                        }
+                    }
+                    None => {
+                        eprintln!("Error: You typed --level-offset, but didn't specify the offset afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    }
+                }
+            }
+            "--flat" => {
+                flat = true;
+            }
             "-o" => {
-                match args.next() {
-                    Some(path) => out_path = path,
+                match next_value(&mut value, &mut args) {
+                    Some(path) => out_path = Some(path),
                     None => {
                         eprintln!("Error: You typed -o, but didn't specify what the file is afterwards.");
-                        return ExitCode::from(1);
+                        return ExitCode::from(EXIT_USAGE);
+                    },
+                }
+            }
+            "--stdout" => {
+                to_stdout = true;
+            }
+            "--split-dir" => {
+                match next_value(&mut value, &mut args) {
+                    Some(dir) => split_dir = Some(dir),
+                    None => {
+                        eprintln!("Error: You typed --split-dir, but didn't specify the directory afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
                     },
                 }
             }
+            "--output-dir" => {
+                match next_value(&mut value, &mut args) {
+                    Some(dir) => output_dir = Some(dir),
+                    None => {
+                        eprintln!("Error: You typed --output-dir, but didn't specify the directory afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    },
+                }
+            }
+            "--split-by-year" => {
+                split_by_year = true;
+            }
+            "--bucket" => {
+                bucket = match next_value(&mut value, &mut args) {
+                    Some(what) => {
+                        match what.as_str() {
+                            "weekly" => Some(Bucket::Weekly),
+                            "monthly" => Some(Bucket::Monthly),
+                            "quarterly" => Some(Bucket::Quarterly),
+                            "yearly" => Some(Bucket::Yearly),
+                            &_ => {
+                                eprintln!("Error: --bucket is either 'weekly', 'monthly', 'quarterly', or 'yearly'.");
+                                return ExitCode::from(EXIT_USAGE);
+                            }
+                        }
+                    }
+                    None => {
+                        eprintln!("Error: You typed --bucket, but didn't specify the bucket afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    }
+                }
+            }
+            "--emit-empty-groups" => {
+                emit_empty_groups = true;
+            }
+            "--allow-output-in-source" => {
+                allow_output_in_source = true;
+            }
+            "--fail-if-changed" => {
+                fail_if_changed = true;
+            }
+            "--append" => {
+                append = true;
+            }
+            "--gzip" => {
+                gzip = true;
+            }
             "--start-date" => {
-                start_date = match try_parse_date(&args.next().unwrap()) {
+                let Some(date) = next_value(&mut value, &mut args) else {
+                    eprintln!("Error: You typed --start-date, but didn't specify the date afterwards.");
+                    return ExitCode::from(EXIT_USAGE);
+                };
+                start_date = match try_parse_date_bound(&date, false) {
                     Ok(d) => {
                         date_bounds_specified = true;
+                        start_date_specified = true;
                         d
                     },
                     Err(e) => {
                         eprintln!("Error: {e}");
-                        return ExitCode::from(1);
+                        return ExitCode::from(EXIT_USAGE);
                     }
                 }
             }
+            "--tag" => {
+                match next_value(&mut value, &mut args) {
+                    Some(tag) => tags.push(tag),
+                    None => {
+                        eprintln!("Error: You typed --tag, but didn't specify the tag afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    },
+                }
+            }
+            "--since" => {
+                match next_value(&mut value, &mut args) {
+                    Some(text) => since = Some(text),
+                    None => {
+                        eprintln!("Error: You typed --since, but didn't specify how long ago afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    }
+                }
+            }
+            "--between" => {
+                let Some(range) = next_value(&mut value, &mut args) else {
+                    eprintln!("Error: You typed --between, but didn't specify the dates afterwards.");
+                    return ExitCode::from(EXIT_USAGE);
+                };
+                let Some((start, end)) = range.split_once("..").filter(|(start, end)| !start.is_empty() || !end.is_empty()) else {
+                    eprintln!("Error: --between expects START..END (either one may be left out), got '{}'.", range);
+                    return ExitCode::from(EXIT_USAGE);
+                };
+
+                if !start.is_empty() {
+                    start_date = match try_parse_date_bound(start, false) {
+                        Ok(d) => d,
+                        Err(e) => {
+                            eprintln!("Error: {e}");
+                            return ExitCode::from(EXIT_USAGE);
+                        }
+                    };
+                    start_date_specified = true;
+                }
+                if !end.is_empty() {
+                    end_date = match try_parse_date_bound(end, true) {
+                        Ok(d) => d,
+                        Err(e) => {
+                            eprintln!("Error: {e}");
+                            return ExitCode::from(EXIT_USAGE);
+                        }
+                    };
+                }
+                date_bounds_specified = true;
+            }
+            "--newest-per-dir" => {
+                newest_per_dir = true;
+            }
+            "--include-undated" => {
+                include_undated = Some(true);
+            }
+            "--exclude-undated" => {
+                include_undated = Some(false);
+            }
             "--end-date" => {
-                end_date = match try_parse_date(&args.next().unwrap()) {
+                let Some(date) = next_value(&mut value, &mut args) else {
+                    eprintln!("Error: You typed --end-date, but didn't specify the date afterwards.");
+                    return ExitCode::from(EXIT_USAGE);
+                };
+                end_date = match try_parse_date_bound(&date, true) {
                     Ok(d) => {
                         date_bounds_specified = true;
                         d
                     },
                     Err(e) => {
                         eprintln!("Error: {e}");
-                        return ExitCode::from(1);
+                        return ExitCode::from(EXIT_USAGE);
                     }
                 }
             }
             "--imglink" => {
                 replace_images_with_links = true;
             }
+            "--rewrite-image-paths" => {
+                rewrite_image_paths = true;
+            }
+            "--force-imagesdir" => {
+                match next_value(&mut value, &mut args) {
+                    Some(dir) if dir.is_empty() => {
+                        eprintln!("Error: --force-imagesdir can't be empty.");
+                        return ExitCode::from(EXIT_USAGE);
+                    }
+                    Some(dir) => force_imagesdir = Some(dir),
+                    None => {
+                        eprintln!("Error: You typed --force-imagesdir, but didn't specify the value afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    },
+                }
+            }
+            "--imagesdir-base" => {
+                match next_value(&mut value, &mut args) {
+                    Some(dir) => match path::absolute(&dir) {
+                        Ok(dir) => imagesdir_base = Some(dir),
+                        Err(err) => {
+                            eprintln!("Error: {}", error_with_file(Path::new(&dir), err));
+                            return ExitCode::from(EXIT_USAGE);
+                        }
+                    },
+                    None => {
+                        eprintln!("Error: You typed --imagesdir-base, but didn't specify the directory afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    },
+                }
+            }
+            "--annotate-mtime" => {
+                annotate_mtime = true;
+            }
+            "--strip-leading-empty" => {
+                strip_leading_empty = true;
+            }
+            "--date-anchors" => {
+                date_anchors = true;
+            }
+            "--toc" => {
+                toc = true;
+            }
+            "--anchors" => {
+                anchors = true;
+            }
+            "--strict-revdate-position" => {
+                strict_revdate_position = true;
+            }
+            "--keep-bom" => {
+                keep_bom = true;
+            }
+            "--strict" => {
+                strict = true;
+            }
+            "--group-by-year" => {
+                group_by_year = true;
+            }
+            "--line-endings" => {
+                line_endings = match next_value(&mut value, &mut args) {
+                    Some(endings) => {
+                        match endings.as_str() {
+                            "lf" => LineEndings::Lf,
+                            "crlf" => LineEndings::Crlf,
+                            "preserve" => LineEndings::Preserve,
+                            &_ => {
+                                eprintln!("Error: --line-endings is either 'lf', 'crlf', or 'preserve'.");
+                                return ExitCode::from(EXIT_USAGE);
+                            }
+                        }
+                    }
+                    None => {
+                        eprintln!("Error: You typed --line-endings, but didn't specify them afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    }
+                }
+            }
+            "--group-by-month" => {
+                group_by_month = true;
+            }
+            "--month-names" => {
+                match next_value(&mut value, &mut args) {
+                    Some(names) => {
+                        month_names = names.split(',').map(|name| name.trim().to_string()).collect();
+                        if month_names.len() != 12 || month_names.iter().any(|name| name.is_empty()) {
+                            eprintln!("Error: --month-names expects twelve comma-separated names, got '{}'.", names);
+                            return ExitCode::from(EXIT_USAGE);
+                        }
+                    }
+                    None => {
+                        eprintln!("Error: You typed --month-names, but didn't specify the names afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    }
+                }
+            }
+            "--source-comments" => {
+                source_comments = true;
+            }
+            "--count" => {
+                count_only = true;
+            }
+            "--dry-run" => {
+                dry_run = true;
+            }
+            "--progress" => {
+                progress = true;
+            }
+            "--require-revdate" => {
+                require_revdate = true;
+            }
+            "--keep-going" => {
+                keep_going = true;
+            }
+            "--quiet" => {
+                quiet = true;
+            }
+            "--verbose" => {
+                verbose = true;
+            }
+            "--strict-paths" => {
+                strict_paths = true;
+            }
+            "--list-authors" => {
+                authors_only = true;
+            }
+            "--check-monotonic" => {
+                check_monotonic_dates = true;
+            }
+            "--report-unknown-attributes" => {
+                report_unknown_attributes = true;
+            }
+            "--only-changed" => {
+                match next_value(&mut value, &mut args) {
+                    Some(path) => changed_cache = Some(path),
+                    None => {
+                        eprintln!("Error: You typed --only-changed, but didn't specify the cache file afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    },
+                }
+            }
+            "--watch" => {
+                watch_sources = true;
+            }
+            "--follow-includes" => {
+                follow_includes = true;
+            }
+            "--summary" => {
+                summary = true;
+            }
+            "--report-gaps" => {
+                gaps = true;
+            }
+            "--report-duplicates" => {
+                duplicates_only = true;
+            }
+            "--json" => {
+                match next_value(&mut value, &mut args) {
+                    Some(path) => json_path = Some(path),
+                    None => {
+                        eprintln!("Error: You typed --json, but didn't specify what the file is afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    },
+                }
+            }
+            "--html-index" => {
+                match next_value(&mut value, &mut args) {
+                    Some(path) => html_index_path = Some(path),
+                    None => {
+                        eprintln!("Error: You typed --html-index, but didn't specify what the file is afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    },
+                }
+            }
+            "--manifest" => {
+                match next_value(&mut value, &mut args) {
+                    Some(path) => manifest_path = Some(path),
+                    None => {
+                        eprintln!("Error: You typed --manifest, but didn't specify what the file is afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    },
+                }
+            }
+            "--base-url" => {
+                match next_value(&mut value, &mut args) {
+                    Some(url) => base_url = url,
+                    None => {
+                        eprintln!("Error: You typed --base-url, but didn't specify the URL afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    },
+                }
+            }
+            "--ext" => {
+                match next_value(&mut value, &mut args) {
+                    Some(ext) => extensions.push(ext.trim_start_matches('.').to_lowercase()),
+                    None => {
+                        eprintln!("Error: You typed --ext, but didn't specify the extension afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    },
+                }
+            }
+            "--exclude" => {
+                match next_value(&mut value, &mut args) {
+                    Some(pattern) => exclude.push(pattern),
+                    None => {
+                        eprintln!("Error: You typed --exclude, but didn't specify the pattern afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    },
+                }
+            }
+            "--limit" => {
+                limit = match next_value(&mut value, &mut args) {
+                    Some(n) => {
+                        match n.parse() {
+                            Ok(n) => Some(n),
+                            Err(_) => {
+                                eprintln!("Error: --limit expects a non-negative number, got '{}'.", n);
+                                return ExitCode::from(EXIT_USAGE);
+                            }
+                        }
+                    }
+                    None => {
+                        eprintln!("Error: You typed --limit, but didn't specify the number afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    }
+                }
+            }
+            "--max-depth" => {
+                max_depth = match next_value(&mut value, &mut args) {
+                    Some(depth) => {
+                        match depth.parse() {
+                            Ok(depth) => Some(depth),
+                            Err(_) => {
+                                eprintln!("Error: --max-depth expects a non-negative number, got '{}'.", depth);
+                                return ExitCode::from(EXIT_USAGE);
+                            }
+                        }
+                    }
+                    None => {
+                        eprintln!("Error: You typed --max-depth, but didn't specify the depth afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    }
+                }
+            }
+            "--include-path-regex" => {
+                match next_value(&mut value, &mut args) {
+                    Some(pattern) => include_path_regex.push(pattern),
+                    None => {
+                        eprintln!("Error: You typed --include-path-regex, but didn't specify the pattern afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    },
+                }
+            }
+            "--exclude-path-regex" => {
+                match next_value(&mut value, &mut args) {
+                    Some(pattern) => exclude_path_regex.push(pattern),
+                    None => {
+                        eprintln!("Error: You typed --exclude-path-regex, but didn't specify the pattern afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    },
+                }
+            }
+            "--format" => {
+                format = match next_value(&mut value, &mut args) {
+                    Some(what) => {
+                        match what.as_str() {
+                            "adoc" => Format::Adoc,
+                            "json" => Format::Json,
+                            &_ => {
+                                eprintln!("Error: --format is either 'adoc' or 'json'.");
+                                return ExitCode::from(EXIT_USAGE);
+                            }
+                        }
+                    }
+                    None => {
+                        eprintln!("Error: You typed --format, but didn't specify the format afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    }
+                }
+            }
             "--order-by" => {
-                order_by = match args.next() {
+                order_by = match next_value(&mut value, &mut args) {
                     Some(what) => {
                         match what.as_str() {
                             "revdate" => OrderBy::Revdate,
@@ -441,133 +1110,487 @@ fn main() -> ExitCode {
                             "id" => OrderBy::ID,
                             &_ => {
                                 eprintln!("Error: --order-by is either 'revdate', 'title', or 'id'.");
-                                return ExitCode::from(1);
+                                return ExitCode::from(EXIT_USAGE);
                             }
                         }
                     }
                     None => {
                         eprintln!("Error: You typed --order-by, but didn't specify what to order by.");
-                        return ExitCode::from(1);
+                        return ExitCode::from(EXIT_USAGE);
+                    }
+                }
+            }
+            "--ascending" => {
+                ascending = true;
+            }
+            "--descending" => {
+                ascending = false;
+            }
+            "--reverse-within-day" => {
+                reverse_within_day = true;
+            }
+            "--title-from-filename" => {
+                derive_titles = true;
+            }
+            "--filename-title-delimiter" => {
+                match next_value(&mut value, &mut args) {
+                    Some(delimiter) => filename_title_delimiter = Some(delimiter),
+                    None => {
+                        eprintln!("Error: You typed --filename-title-delimiter, but didn't specify the delimiter afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
+                    },
+                }
+            }
+            "--missing-title" => {
+                missing_title = match next_value(&mut value, &mut args) {
+                    Some(what) => {
+                        match what.as_str() {
+                            "first" => MissingTitle::First,
+                            "last" => MissingTitle::Last,
+                            "filename" => MissingTitle::Filename,
+                            &_ => {
+                                eprintln!("Error: --missing-title is either 'first', 'last', or 'filename'.");
+                                return ExitCode::from(EXIT_USAGE);
+                            }
+                        }
+                    }
+                    None => {
+                        eprintln!("Error: You typed --missing-title, but didn't specify the policy afterwards.");
+                        return ExitCode::from(EXIT_USAGE);
                     }
                 }
             }
             _ => {
-                src_dirs.push(arg);
+                src_dirs.push(arg.clone());
             }
         }
+
+        if let Some(value) = value {
+            eprintln!("Error: {} doesn't take a value, but got '{}'.", arg, value);
+            return ExitCode::from(EXIT_USAGE);
+        }
    }
 
-    if src_dirs.len() == 0 {
+    if src_dirs.is_empty() {
         usage();
         eprintln!("Error: No source directories provided.");
-        return ExitCode::from(1);
+        return ExitCode::from(EXIT_USAGE);
+    }
+
+    if watch_sources && src_dirs.iter().any(|dir| dir == "-") {
+        eprintln!("Error: --watch can't read the list of files from stdin.");
+        return ExitCode::from(EXIT_USAGE);
     }
 
-    // TODO: unwrap
+    if split_by_year != output_dir.is_some() {
+        eprintln!("Error: --split-by-year and --output-dir only work together.");
+        return ExitCode::from(EXIT_USAGE);
+    }
+    if split_by_year && (split_dir.is_some() || bucket.is_some() || out_path.is_some() || to_stdout) {
+        eprintln!("Error: --split-by-year can't be combined with -o, --stdout, --split-dir, or --bucket.");
+        return ExitCode::from(EXIT_USAGE);
+    }
+    // --split-by-year writes the calendars like --split-dir does, just named
+    // calendar-YYYY.adoc and with an index.
+    let split_opts = SplitOptions {
+        bucket: bucket.unwrap_or(Bucket::Yearly),
+        emit_empty: emit_empty_groups,
+        prefix: if split_by_year { "calendar-" } else { "" },
+        index: split_by_year,
+    };
+    if output_dir.is_some() {
+        split_dir = output_dir;
+    }
 
-    let header = if let Some(path) = header_path {
-        fs::read_to_string(path).unwrap()
+    if to_stdout && (out_path.is_some() || split_dir.is_some() || fail_if_changed) {
+        eprintln!("Error: --stdout can't be combined with -o, --split-dir, or --fail-if-changed.");
+        return ExitCode::from(EXIT_USAGE);
+    }
+    if append && (to_stdout || split_dir.is_some() || fail_if_changed || toc) {
+        eprintln!("Error: --append can't be combined with --stdout, --split-dir, --split-by-year, --fail-if-changed, or --toc.");
+        return ExitCode::from(EXIT_USAGE);
+    }
+    if to_stdout || count_only {
+        INFO_TO_STDERR.store(true, AtomicOrdering::Relaxed);
+    }
+    // --quiet wins over --verbose.
+    set_verbosity(if quiet { Verbosity::Quiet } else if verbose { Verbosity::Verbose } else { Verbosity::Normal });
+    if gzip && !cfg!(feature = "gzip") {
+        eprintln!("Error: This build has no gzip support (rebuild with --features gzip).");
+        return ExitCode::from(EXIT_USAGE);
+    }
+    if gzip && (split_dir.is_some() || append) {
+        eprintln!("Error: --gzip can't be combined with --split-dir, --split-by-year, or --append.");
+        return ExitCode::from(EXIT_USAGE);
+    }
+    if manifest_path.is_some() && (split_dir.is_some() || append) {
+        eprintln!("Error: --manifest can't be combined with --split-dir, --split-by-year, or --append.");
+        return ExitCode::from(EXIT_USAGE);
+    }
+    if matches!(format, Format::Json) && (split_dir.is_some() || append || gzip || manifest_path.is_some()) {
+        eprintln!("Error: --format json can't be combined with --split-dir, --split-by-year, --append, --gzip, or --manifest.");
+        return ExitCode::from(EXIT_USAGE);
+    }
+    let out_path = out_path.unwrap_or(String::from(match format {
+        Format::Json => "calendar.json",
+        Format::Adoc if gzip => "calendar.adoc.gz",
+        Format::Adoc => "calendar.adoc",
+    }));
+
+    if split_dir.is_none() && (bucket.is_some() || emit_empty_groups) {
+        eprintln!("Error: --bucket and --emit-empty-groups only make sense with --split-dir or --split-by-year.");
+        return ExitCode::from(EXIT_USAGE);
+    }
+
+    if let Some(text) = since {
+        if start_date_specified {
+            eprintln!("Error: --since can't be combined with --start-date.");
+            return ExitCode::from(EXIT_USAGE);
+        }
+
+        start_date = match parse_since(&text, Date::today()) {
+            Ok(date) => date,
+            Err(err) => {
+                eprintln!("Error: {err}");
+                return ExitCode::from(EXIT_USAGE);
+            }
+        };
+        date_bounds_specified = true;
+    }
+
+    // A section can only have one id, so the date anchor and the TOC anchor
+    // would fight over it.
+    if toc && date_anchors {
+        eprintln!("Error: --toc can't be combined with --date-anchors.");
+        return ExitCode::from(EXIT_USAGE);
+    }
+    if anchors && date_anchors {
+        eprintln!("Error: --anchors can't be combined with --date-anchors.");
+        return ExitCode::from(EXIT_USAGE);
+    }
+    if html_index_path.is_some() && date_anchors {
+        eprintln!("Error: --html-index can't be combined with --date-anchors.");
+        return ExitCode::from(EXIT_USAGE);
+    }
+    // The HTML index links to the document anchors, so they have to be written.
+    let anchors = anchors || (html_index_path.is_some() && !toc);
+
+    if filename_title_delimiter.is_some() && !derive_titles {
+        eprintln!("Error: --filename-title-delimiter only makes sense with --title-from-filename.");
+        return ExitCode::from(EXIT_USAGE);
+    }
+
+    if rewrite_image_paths && (replace_images_with_links || force_imagesdir.is_some()) {
+        eprintln!("Error: --rewrite-image-paths can't be combined with --imglink or --force-imagesdir.");
+        return ExitCode::from(EXIT_USAGE);
+    }
+
+    let zip_sources = src_dirs.iter().any(|dir| is_zip(Path::new(dir)));
+    if zip_sources && !cfg!(feature = "zip") {
+        eprintln!("Error: This build has no zip support (rebuild with --features zip).");
+        return ExitCode::from(EXIT_USAGE);
+    }
+    if zip_sources && (follow_includes || rewrite_image_paths) {
+        eprintln!("Error: --follow-includes and --rewrite-image-paths can't be used with .zip sources.");
+        return ExitCode::from(EXIT_USAGE);
+    }
+
+    if flat && level_offset.is_some() {
+        eprintln!("Error: --flat can't be combined with --level-offset.");
+        return ExitCode::from(EXIT_USAGE);
+    }
+
+    if title.is_some() && header_path.is_some() {
+        eprintln!("Error: --title can't be combined with --header.");
+        return ExitCode::from(EXIT_USAGE);
+    }
+    if header_from_first_doc && (title.is_some() || header_path.is_some()) {
+        eprintln!("Error: --header-from-first-doc can't be combined with --header or --title.");
+        return ExitCode::from(EXIT_USAGE);
+    }
+
+    let watched_files: Vec<PathBuf> = header_path.iter().chain(footer_path.iter()).map(PathBuf::from)
+        .chain(config_file)
+        .collect();
+
+    // A --header file brings its own title, which --header-level doesn't describe.
+    let default_level_offset = if header_path.is_some() { 1 } else { header_level as i8 + 1 };
+
+    let mut header = if let Some(path) = header_path {
+        match fs::read_to_string(&path) {
+            Ok(header) => header,
+            Err(err) => {
+                eprintln!("Error: {}", error_with_file(Path::new(&path), err));
+                return ExitCode::from(EXIT_SOURCE);
+            }
+        }
     } else {
-        String::from("= Calendar\n\n")
+        let title = title.as_deref().unwrap_or("Calendar");
+        format!("{} {}\n\n", "=".repeat(header_level as usize + 1), title)
     };
 
     let footer = if let Some(path) = footer_path {
-        fs::read_to_string(path).unwrap()
+        match fs::read_to_string(&path) {
+            Ok(footer) => footer,
+            Err(err) => {
+                eprintln!("Error: {}", error_with_file(Path::new(&path), err));
+                return ExitCode::from(EXIT_SOURCE);
+            }
+        }
     } else {
         String::from("")
     };
 
+    let path_filter = match PathFilter::new(&include_path_regex, &exclude_path_regex) {
+        Ok(filter) => filter,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    if extensions.is_empty() {
+        extensions = DEFAULT_EXTENSIONS.iter().map(|ext| ext.to_string()).collect();
+    }
+
+    let traverse_opts = TraverseOptions {
+        filter: path_filter,
+        max_depth,
+        extensions,
+        exclude,
+    };
+
+    if !to_stdout && !dry_run && !count_only && !allow_output_in_source {
+        let (out, is_dir) = match split_dir {
+            Some(ref dir) => (dir.as_str(), true),
+            None => (out_path.as_str(), false),
+        };
+        if let Some(dir) = source_dir_containing(Path::new(out), is_dir, &src_dirs, &traverse_opts.extensions) {
+            eprintln!(
+                "Error: The output '{}' is inside the source directory '{}', so the next run would include it.",
+                out, dir,
+            );
+            eprintln!("Use --allow-output-in-source if that's intended.");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    }
+
+    if watch_sources {
+        let out = split_dir.as_deref().unwrap_or(if to_stdout { "stdout" } else { &out_path });
+        return watch(&src_dirs, &watched_files, &traverse_opts, out);
+    }
+
     let perf_traverse = Instant::now();
 
-    let mut files: HashSet<PathBuf> = HashSet::new();
+    let mut found = FoundFiles::default();
+    // The progress line is rewritten in place, which only works on a terminal,
+    // and would get in the way of the --verbose lines.
+    if progress && io::stderr().is_terminal() && verbosity() == Verbosity::Normal {
+        found.progress = Some(Instant::now());
+    }
 
     for dir in src_dirs {
+        if dir == "-" {
+            if let Err(err) = read_listed_files(io::stdin().lock(), &mut found) {
+                eprintln!("Error: {err}");
+                return ExitCode::from(EXIT_SOURCE);
+            }
+            continue;
+        }
+
         let path = Path::new(&dir);
 
         if !path.exists() {
             eprintln!("Error: Source directory '{}' does not exist.", path.display());
-            return ExitCode::from(1);
+            return ExitCode::from(EXIT_SOURCE);
         }
 
-        if !path.is_dir() {
-            eprintln!("Error: Source path '{}' is not a directory.", path.display());
-            return ExitCode::from(1);
+        if !path.is_dir() && !is_zip(path) {
+            eprintln!("Error: Source path '{}' is not a directory or a .zip archive.", path.display());
+            return ExitCode::from(EXIT_SOURCE);
         }
 
-        match get_adoc_files(path, &mut files) {
+        match get_adoc_files(path, &traverse_opts, &[], 0, &mut found) {
             Ok(_) => {},
             Err(err) => {
                 eprintln!("Error: {err}");
-                return ExitCode::from(1);
+                return ExitCode::from(EXIT_SOURCE);
             }
         };
     }
 
+    found.finish_progress();
+
     let perf_traverse = perf_traverse.elapsed();
 
-    println!("AsciiDoc files found: {}.", files.len());
+    let files = found.files;
+    let entries = found.entries;
+    let scanned = found.scanned;
+
+    info!("AsciiDoc files found: {}.", files.len() + entries.len());
 
     let perf_parse = Instant::now();
 
+    let parse_opts = ParseOptions {
+        replace_images_with_links,
+        rewrite_image_paths,
+        keep_bom,
+        force_imagesdir: force_imagesdir.clone(),
+        imagesdir_base: imagesdir_base.clone(),
+        warn_misplaced_revdate: strict_revdate_position,
+        follow_includes,
+        preserve_line_endings: matches!(line_endings, LineEndings::Preserve),
+    };
+
     let mut docs: Vec<Doc> = Vec::new();
-    for path in files {
-        let doc = parse_doc(&path, replace_images_with_links).unwrap();
-        if let Some(doc) = doc {
+    let mut count_failed = 0;
+    let sources = files.iter().map(|path| (path, None)).chain(entries.iter().map(|entry| (&entry.path, Some(entry))));
+    for (path, entry) in sources {
+        // Document paths are stored lossily, so a non-UTF-8 path would end up
+        // pointing at a file that doesn't exist.
+        if strict_paths && path.to_str().is_none() {
+            let err = error_with_file(path, error(String::from("Path is not valid UTF-8.")));
+            eprintln!("Error: {err}");
+            return ExitCode::from(EXIT_SOURCE);
+        }
+
+        let parsed = match entry {
+            Some(entry) => parse_entry(entry, &parse_opts),
+            None => parse_doc(path, &parse_opts),
+        };
+        let doc = match parsed {
+            Ok(doc) => doc,
+            Err(err) if keep_going => {
+                warning(format!("{err} (skipped)"));
+                count_failed += 1;
+                continue;
+            }
+            Err(err) => {
+                eprintln!("Error: {err}");
+                return ExitCode::from(EXIT_PARSE);
+            }
+        };
+        if let Some(mut doc) = doc {
+            if derive_titles && doc.title.is_none() {
+                doc.title = Some(title_from_filename(&doc.path, filename_title_delimiter.as_deref()));
+            }
             docs.push(doc);
-        } else {
-            // It had include::[].
         }
     }
 
     let perf_parse = perf_parse.elapsed();
 
-    let perf_output = Instant::now();
+    if newest_per_dir {
+        keep_newest_per_dir(&mut docs);
+    }
 
-    match order_by {
-        OrderBy::Revdate => {
-            docs.sort_by(|a, b| {
-                // Sort by revdates in descending order (newest on the top).
+    if check_monotonic_dates {
+        for (prev, next) in out_of_order(&docs) {
+            warning(format!(
+                "{}: revdate {} is newer than the preceding {} ({}).",
+                next.path, next.revdate.unwrap(), prev.path, prev.revdate.unwrap(),
+            ));
+        }
+    }
 
-                let l = a.revdate;
-                let r = b.revdate;
+    if report_unknown_attributes {
+        let counts = unknown_attributes(&docs);
+        report!("Unknown attributes: {}.", counts.len());
+        for (name, count) in counts {
+            report!("{count:>5}  :{name}:");
+        }
+    }
 
-                if l.is_none() && r.is_none() {
-                    return Ordering::Equal;
-                } else if l.is_none() {
-                    return Ordering::Greater;
-                } else if r.is_none() {
-                    return Ordering::Less;
+    if let Some(path) = changed_cache {
+        match changed_since_last_run(Path::new(&path), &docs) {
+            Ok(changed) => {
+                report!("Documents    changed: {}.", changed.len());
+                for path in changed {
+                    report!("  {}", path);
                 }
+            }
+            Err(err) => {
+                eprintln!("Error: {err}");
+                return ExitCode::from(EXIT_OUTPUT);
+            }
+        }
+    }
 
-                let l = l.unwrap();
-                let r = r.unwrap();
-
-                let y = r.year.cmp(&l.year);
-                let m = r.month.cmp(&l.month);
-                let d = r.day.cmp(&l.day);
+    if duplicates_only {
+        let groups = match duplicate_groups(&docs) {
+            Ok(groups) => groups,
+            Err(err) => {
+                eprintln!("Error: {err}");
+                return ExitCode::from(EXIT_SOURCE);
+            }
+        };
+        if groups.is_empty() {
+            println!("No duplicate documents found.");
+        }
+        for (i, group) in groups.iter().enumerate() {
+            println!("Duplicate group {}:", i + 1);
+            for path in group {
+                println!("  {}", path);
+            }
+        }
+        return ExitCode::SUCCESS;
+    }
 
-                if y != Ordering::Equal { return y; }
-                if m != Ordering::Equal { return m; }
-                if d != Ordering::Equal { return d; }
+    let perf_output = Instant::now();
 
-                Ordering::Equal
+    // Every order falls back to the path (ascending) for documents that compare
+    // equal, so the result doesn't depend on the order read_dir returned them in.
+    match order_by {
+        OrderBy::Revdate => {
+            docs.sort_by(|a, b| {
+                // Sort by revdates in descending order (newest on the top), or in
+                // ascending order with --ascending. Undated documents always go last.
+                // Documents with the same revdate are ordered by path, so that the
+                // output doesn't depend on the order the files were found in,
+                // descending with --reverse-within-day.
+
+                // None compares less than any date, so comparing in reverse
+                // puts undated documents last. A time orders documents within
+                // the day, without one they count as 00:00.
+                let by_date = match (a.revdate, b.revdate) {
+                    (Some(l), Some(r)) => {
+                        let l = (l, a.revtime.unwrap_or_default());
+                        let r = (r, b.revtime.unwrap_or_default());
+                        if ascending { l.cmp(&r) } else { r.cmp(&l) }
+                    }
+                    _ => b.revdate.cmp(&a.revdate),
+                };
+                by_date.then_with(|| {
+                    if reverse_within_day && a.revdate.is_some() {
+                        b.path.cmp(&a.path)
+                    } else {
+                        a.path.cmp(&b.path)
+                    }
+                })
             });
         }
 
         OrderBy::Title => {
             docs.sort_by(|a, b| {
-                let l = &a.title;
-                let r = &b.title;
-
-                if l == "" && r == "" {
-                    return Ordering::Equal;
-                } else if l == "" {
-                    return Ordering::Greater;
-                } else if r == "" {
-                    return Ordering::Less;
+                let (l, r) = match missing_title {
+                    MissingTitle::Filename => (title_or_filename(a), title_or_filename(b)),
+                    _ => (a.title.as_deref().unwrap_or(""), b.title.as_deref().unwrap_or("")),
+                };
+
+                let missing = match missing_title {
+                    MissingTitle::First => Ordering::Less,
+                    _ => Ordering::Greater,
+                };
+
+                if l.is_empty() && r.is_empty() {
+                    return a.path.cmp(&b.path);
+                } else if l.is_empty() {
+                    return missing;
+                } else if r.is_empty() {
+                    return missing.reverse();
                 }
 
-                l.cmp(&r)
+                l.cmp(r).then_with(|| a.path.cmp(&b.path))
             });
         }
 
@@ -576,47 +1599,231 @@ fn main() -> ExitCode {
                 let l = &a.id;
                 let r = &b.id;
 
-                if l == "" && r == "" {
-                    return Ordering::Equal;
-                } else if l == "" {
+                if l.is_empty() && r.is_empty() {
+                    return a.path.cmp(&b.path);
+                } else if l.is_empty() {
                     return Ordering::Greater;
-                } else if r == "" {
+                } else if r.is_empty() {
                     return Ordering::Less;
                 }
 
-                l.cmp(&r)
+                l.cmp(r).then_with(|| a.path.cmp(&b.path))
             });
         }
     }
 
-    let docs_filtered = docs.iter().filter(|doc| {
-        if let Some(date) = doc.revdate {
-            date.ge(&start_date) && date.le(&end_date)
+    if let Some(path) = json_path.filter(|_| !dry_run && !count_only) {
+        let docs: Vec<&Doc> = docs.iter().collect();
+        if let Err(err) = write_json(Path::new(&path), &docs, false) {
+            eprintln!("Error: {}", err);
+            return ExitCode::from(EXIT_OUTPUT);
+        }
+    }
+
+    if require_revdate {
+        let undated: Vec<&Doc> = docs.iter().filter(|doc| doc.revdate.is_none()).collect();
+        if !undated.is_empty() {
+            eprintln!("Error: {} document(s) without a :revdate: entry:", undated.len());
+            for doc in undated {
+                eprintln!("  {}", doc.path);
+            }
+            return ExitCode::from(EXIT_PARSE);
+        }
+    }
+
+    let mut kept = 0;
+    let docs_filtered: Vec<&Doc> = docs.iter().filter(|doc| {
+        let keep = if let Some(date) = doc.revdate {
+            date >= start_date && date <= end_date
         } else {
-            !date_bounds_specified
+            include_undated.unwrap_or(!date_bounds_specified)
+        };
+
+        if keep && !tags.is_empty() && !doc.tags.iter().any(|tag| tags.contains(tag)) {
+            verbose!("skipped (no matching tag): {}", doc.path);
+            return false;
         }
-    });
 
-    match generate(&out_path, &header, &footer, docs_filtered) {
-        Ok(count) => {
-            println!("Documents   included: {count}.");
+        // The documents are sorted by now, so these are the first ones in order.
+        if keep && limit.is_some_and(|limit| kept >= limit) {
+            verbose!("skipped (over --limit): {}", doc.path);
+            return false;
+        }
+        if keep {
+            kept += 1;
+        }
+
+        if !keep {
+            if doc.revdate.is_none() {
+                warning(format!("{}: no :revdate: found, skipping", doc.path));
+                verbose!("skipped (no revdate after filtering): {}", doc.path);
+            } else {
+                verbose!("skipped (outside date bounds): {}", doc.path);
+            }
+        } else {
+            verbose!("included: {}", doc.path);
+        }
+        keep
+    }).collect();
+
+    let duplicates = duplicate_revdates(&docs_filtered);
+    for (date, time, a, b) in &duplicates {
+        let revdate = match time {
+            Some(time) => format!("{} {}", date, time),
+            None => date.to_string(),
+        };
+        let text = format!("Duplicate revdate {} in {} and {} (ordered by path).", revdate, a, b);
+        if strict {
+            eprintln!("Error: {}", text);
+        } else {
+            warning(text);
+        }
+    }
+    if strict && !duplicates.is_empty() {
+        return ExitCode::from(EXIT_PARSE);
+    }
+
+    if gaps {
+        for (first, last, missing) in date_gaps(&docs_filtered) {
+            report!("gap: {} .. {} ({} {})", first, last, missing, if missing == 1 { "day" } else { "days" });
+        }
+    }
+
+    if authors_only {
+        for (author, count) in author_counts(docs_filtered.iter().copied()) {
+            println!("{count:>5}  {author}");
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    if count_only {
+        println!("{}", docs_filtered.len());
+        return ExitCode::SUCCESS;
+    }
+
+    if dry_run {
+        for doc in &docs_filtered {
+            let date = doc.revdate.map(|date| date.to_string()).unwrap_or(String::from("-"));
+            eprintln!("{:<10}  {}", date, doc.path);
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    if let Some(path) = html_index_path {
+        if let Err(err) = generate_html_index(Path::new(&path), &base_url, &docs_filtered, anchors) {
+            eprintln!("Error: {}", err);
+            return ExitCode::from(EXIT_OUTPUT);
+        }
+    }
+
+    // The first document is only known once they're filtered and sorted.
+    if let Some(doc) = docs_filtered.first().filter(|_| header_from_first_doc) {
+        header = format!("{} {}\n", "=".repeat(header_level as usize + 1), title_or_filename(doc));
+        // Author and revision lines only mean something in a document header.
+        if header_level == 0 {
+            if let Some(ref author) = doc.author {
+                header.push_str(&format!("{}\n", author));
+            }
+            if let Some(date) = doc.revdate {
+                header.push_str(&format!(":revdate: {}\n", date));
+            }
+        }
+        header.push('\n');
+    }
+
+    let gen_opts = GenerateOptions {
+        header,
+        footer,
+        level_offset: level_offset.unwrap_or(default_level_offset),
+        flat,
+        force_imagesdir,
+        imagesdir_base,
+        annotate_mtime,
+        source_comments,
+        strip_leading_empty,
+        date_anchors,
+        toc,
+        anchors,
+        group_by_year,
+        group_by_month,
+        month_names,
+        line_endings,
+        append,
+        gzip,
+        separator,
+    };
+
+    // No ranges where --manifest can't be used.
+    let generated = match format {
+        Format::Json if to_stdout => {
+            write_json_array(io::stdout().lock(), docs_filtered.iter().copied()).map(|count| (count, true, Vec::new()))
+        }
+        Format::Json => {
+            write_json(Path::new(&out_path), &docs_filtered, fail_if_changed).map(|(count, changed)| (count, changed, Vec::new()))
+        }
+        Format::Adoc if to_stdout => {
+            generate_output(io::stdout().lock(), &gen_opts, &docs_filtered).map(|ranges| (ranges.len(), true, ranges))
+        }
+        Format::Adoc => match split_dir {
+            Some(ref dir) => write_split(Path::new(dir), &split_opts, &gen_opts, &docs_filtered, fail_if_changed)
+                .map(|(count, changed)| (count, changed, Vec::new())),
+            None => write_calendar(Path::new(&out_path), &gen_opts, &docs_filtered, fail_if_changed)
+                .map(|(ranges, changed)| (ranges.len(), changed, ranges)),
+        },
+    };
+
+    let output_changed = match generated {
+        Ok((count, changed, ranges)) => {
+            if let Some(ref path) = manifest_path {
+                if let Err(err) = write_manifest(Path::new(path), &ranges) {
+                    eprintln!("Error: {err}");
+                    return ExitCode::from(EXIT_OUTPUT);
+                }
+            }
+            info!("Documents   included: {count}.");
+            verbose!("scanned {} files, included {}, skipped {}", scanned, count, scanned.saturating_sub(count));
+            changed
         },
         Err(err) => {
             eprintln!("Error: {err}");
-            return ExitCode::from(1);
+            return ExitCode::from(EXIT_OUTPUT);
         }
     };
 
+    if summary {
+        let undated = docs_filtered.iter().filter(|doc| doc.revdate.is_none()).count();
+        let undated = if undated > 0 { format!(", +{} undated", undated) } else { String::new() };
+        match date_span(&docs_filtered) {
+            Some((first, last, days)) => {
+                let dated = docs_filtered.iter().filter(|doc| doc.revdate.is_some()).count();
+                let unit = if days == 1 { "day" } else { "days" };
+                eprintln!("calendar spans {} to {} ({} {}), {} entries{}", first, last, days, unit, dated, undated);
+            }
+            None => eprintln!("calendar has no dated entries{}", undated),
+        }
+    }
+
     let perf_output = perf_output.elapsed();
 
     let perf_total = perf_total.elapsed();
 
-    println!("");
-    println!("Traverse time: {:.5} s.", perf_traverse.as_secs_f32());
-    println!("Parse    time: {:.5} s.", perf_parse.as_secs_f32());
-    println!("Output   time: {:.5} s.", perf_output.as_secs_f32());
-    println!("Other    time: {:.5} s.", (perf_total - (perf_traverse + perf_parse + perf_output)).as_secs_f32());
-    println!("Total    time: {:.5} s.", perf_total.as_secs_f32());
+    info!();
+    info!("Traverse time: {:.5} s.", perf_traverse.as_secs_f32());
+    info!("Parse    time: {:.5} s.", perf_parse.as_secs_f32());
+    info!("Output   time: {:.5} s.", perf_output.as_secs_f32());
+    info!("Other    time: {:.5} s.", (perf_total - (perf_traverse + perf_parse + perf_output)).as_secs_f32());
+    info!("Total    time: {:.5} s.", perf_total.as_secs_f32());
+
+    if fail_if_changed && output_changed {
+        let out_path = split_dir.unwrap_or(out_path);
+        eprintln!("Error: '{}' was out of date and has been regenerated.", out_path);
+        return ExitCode::from(EXIT_CHANGED);
+    }
+
+    if count_failed > 0 {
+        eprintln!("Error: {} document(s) were left out because they failed to parse.", count_failed);
+        return ExitCode::from(EXIT_SKIPPED);
+    }
 
     ExitCode::SUCCESS
 }
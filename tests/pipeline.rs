@@ -0,0 +1,1487 @@
+use std::cmp::Reverse;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use calendar_fast::{
+    collect, duplicate_revdates, generate, generate_ranges, CollectOptions, Date, Doc, GenerateOptions, LineEndings, ParseOptions, PathFilter,
+    Time, TraverseOptions, DEFAULT_EXTENSIONS, MONTH_NAMES,
+};
+
+// A fresh directory under the system temp directory, removed when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> TempDir {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("calendar-fast-test-{}-{}", std::process::id(), COUNT.fetch_add(1, Ordering::Relaxed));
+        let dir = env::temp_dir().join(name);
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    fn write(&self, name: &str, contents: &[u8]) -> PathBuf {
+        let path = self.0.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn collect_opts() -> CollectOptions {
+    CollectOptions {
+        traverse: TraverseOptions {
+            filter: PathFilter::default(),
+            max_depth: None,
+            extensions: DEFAULT_EXTENSIONS.iter().map(|ext| ext.to_string()).collect(),
+            exclude: Vec::new(),
+        },
+        parse: ParseOptions {
+            replace_images_with_links: false,
+            rewrite_image_paths: false,
+            keep_bom: false,
+            force_imagesdir: None,
+            imagesdir_base: None,
+            warn_misplaced_revdate: false,
+            follow_includes: false,
+            preserve_line_endings: false,
+        },
+    }
+}
+
+fn generate_opts() -> GenerateOptions {
+    GenerateOptions {
+        header: String::from("= Calendar\n\n"),
+        footer: String::new(),
+        source_comments: false,
+        level_offset: 1,
+        flat: false,
+        force_imagesdir: None,
+        imagesdir_base: None,
+        annotate_mtime: false,
+        strip_leading_empty: false,
+        date_anchors: false,
+        toc: false,
+        anchors: false,
+        group_by_year: false,
+        line_endings: LineEndings::Lf,
+        group_by_month: false,
+        month_names: MONTH_NAMES.iter().map(|name| name.to_string()).collect(),
+        append: false,
+        gzip: false,
+        separator: None,
+    }
+}
+
+fn render(docs: &[Doc], opts: &GenerateOptions) -> String {
+    let mut out = Vec::new();
+    generate(&mut out, opts, docs.iter()).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+fn run(src: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+        .arg(src)
+        .args(["--stdout", "--quiet"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn titles(calendar: &str) -> Vec<&str> {
+    calendar.lines().filter_map(|line| line.strip_prefix("= ")).skip(1).collect()
+}
+
+#[test]
+fn newest_first_undated_last() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= Middle\n:revdate: 2024-06-01\n");
+    src.write("b.adoc", b"= Undated\n");
+    src.write("c.adoc", b"= Newest\n:revdate: 2025-01-01\n");
+    src.write("d/e.adoc", b"= Oldest\n:revdate: 2023-12-31\n");
+
+    assert_eq!(titles(&run(&src.0, &[])), ["Newest", "Middle", "Oldest", "Undated"]);
+    assert_eq!(titles(&run(&src.0, &["--ascending"])), ["Oldest", "Middle", "Newest", "Undated"]);
+}
+
+#[test]
+fn revdate_times_order_within_the_day() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= Morning\n:revdate: 2024-06-01 09:00\n");
+    src.write("b.adoc", b"= No time\n:revdate: 2024-06-01\n");
+    src.write("c.adoc", b"= Night\n:revdate: 2024-06-01 23:59: late edit\n");
+    src.write("d.adoc", b"= Next day\n:revdate: 2024-06-02\n");
+    src.write("e.adoc", b"= Remark\n:revdate: 2024-06-01 12 items\n");
+
+    let docs = collect(&src.0, &collect_opts()).unwrap();
+    let time = |title: &str| docs.iter().find(|doc| doc.title.as_deref() == Some(title)).unwrap().revtime;
+    assert_eq!(time("Morning"), Some(Time { hour: 9, minute: 0 }));
+    assert_eq!(time("Night"), Some(Time { hour: 23, minute: 59 }));
+    assert_eq!(time("No time"), None);
+    assert_eq!(time("Remark"), None);
+
+    assert_eq!(titles(&run(&src.0, &[])), ["Next day", "Night", "Morning", "No time", "Remark"]);
+    assert_eq!(titles(&run(&src.0, &["--ascending"])), ["No time", "Remark", "Morning", "Night", "Next day"]);
+    assert!(run(&src.0, &["--format", "json"]).contains("\"revdate\": \"2024-06-01\", \"revtime\": \"09:00\""));
+
+    src.write("f.adoc", b"= Bad\n:revdate: 2024-06-01 24:00\n");
+    let status = Command::new(env!("CARGO_BIN_EXE_calendar-fast")).arg(&src.0).args(["--stdout", "--quiet"]).status();
+    assert_eq!(status.unwrap().code(), Some(4));
+}
+
+#[test]
+fn reverse_within_day() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= A\n:revdate: 2024-06-01\n");
+    src.write("b.adoc", b"= B\n:revdate: 2024-06-01\n");
+    src.write("c.adoc", b"= C\n:revdate: 2024-06-01\n");
+    src.write("d.adoc", b"= D\n:revdate: 2024-01-01\n");
+
+    assert_eq!(titles(&run(&src.0, &[])), ["A", "B", "C", "D"]);
+    assert_eq!(titles(&run(&src.0, &["--reverse-within-day"])), ["C", "B", "A", "D"]);
+    assert_eq!(titles(&run(&src.0, &["--reverse-within-day", "--ascending"])), ["D", "C", "B", "A"]);
+}
+
+#[test]
+fn date_bounds_are_inclusive() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= Before\n:revdate: 2023-12-31\n");
+    src.write("b.adoc", b"= First\n:revdate: 2024-01-01\n");
+    src.write("c.adoc", b"= Last\n:revdate: 2024-06-30\n");
+    src.write("d.adoc", b"= After\n:revdate: 2024-07-01\n");
+    src.write("e.adoc", b"= Undated\n");
+
+    assert_eq!(titles(&run(&src.0, &["--start-date", "2024", "--end-date", "2024-06"])), ["Last", "First"]);
+    assert_eq!(titles(&run(&src.0, &["--start-date", "2024-06-30"])), ["After", "Last"]);
+}
+
+#[test]
+fn limit_takes_the_first_documents() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= One\n:revdate: 2024-01-01\n");
+    src.write("b.adoc", b"= Two\n:revdate: 2024-01-02\n");
+    src.write("c.adoc", b"= Three\n:revdate: 2024-01-03\n");
+    src.write("d.adoc", b"= Four\n:revdate: 2023-01-01\n");
+
+    assert_eq!(titles(&run(&src.0, &["--limit", "2"])), ["Three", "Two"]);
+    assert_eq!(titles(&run(&src.0, &["--limit=2", "--ascending"])), ["Four", "One"]);
+    assert_eq!(titles(&run(&src.0, &["--limit=2", "--ascending", "--start-date", "2024"])), ["One", "Two"]);
+    assert_eq!(titles(&run(&src.0, &["--limit", "10"])).len(), 4);
+    assert_eq!(run(&src.0, &["--limit", "0"]), "= Calendar\n\n\n\n:leveloffset: +1\n\n\n\n:leveloffset: -1\n\n");
+}
+
+#[test]
+fn header_from_first_doc() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= Older\n:revdate: 2024-01-01\n");
+    src.write("b.adoc", b"= Newest\nJane Doe\n:revdate: 2025-01-01\n");
+
+    let calendar = run(&src.0, &["--header-from-first-doc"]);
+    assert!(calendar.starts_with("= Newest\nJane Doe\n:revdate: 2025-01-01\n\n"), "{}", calendar);
+}
+
+#[test]
+fn output_formats() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= A\n:revdate: 2024-01-01\n");
+    src.write("b.adoc", b"= B\n:revdate: 2024-06-01\n");
+    src.write("c.adoc", b"= C\n:revdate: 2023-01-01\n");
+    let between = ["--between", "2024..2024"];
+
+    let adoc = run(&src.0, &[&between[..], &["--format", "adoc"]].concat());
+    assert_eq!(adoc, run(&src.0, &between));
+    assert_eq!(titles(&adoc), ["B", "A"]);
+
+    let json = run(&src.0, &[&between[..], &["--format", "json"]].concat());
+    let rows: Vec<&str> = json.lines().filter(|line| line.contains("\"path\"")).collect();
+    assert!(json.starts_with('[') && json.ends_with("]\n"));
+    assert_eq!(rows.len(), 2);
+    assert!(rows[0].contains("\"revdate\": \"2024-06-01\", \"revtime\": null, \"title\": \"B\""));
+    assert!(rows[1].contains("\"revdate\": \"2024-01-01\", \"revtime\": null, \"title\": \"A\""));
+
+    let status = Command::new(env!("CARGO_BIN_EXE_calendar-fast")).arg(&src.0).args(["--format", "yaml"]).status();
+    assert_eq!(status.unwrap().code(), Some(2));
+}
+
+#[test]
+fn summary_reports_the_span() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= A\n:revdate: 2024-02-27\n");
+    src.write("b.adoc", b"= B\n:revdate: 2024-03-02\n");
+    src.write("c.adoc", b"= C\n:revdate: 2024-02-28\n");
+    src.write("d.adoc", b"= Undated\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+        .arg(&src.0)
+        .args(["--stdout", "--quiet", "--summary"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "calendar spans 2024-02-27 to 2024-03-02 (5 days), 3 entries, +1 undated\n",
+    );
+}
+
+#[test]
+fn exit_codes() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= Undated\n");
+    let exit_code = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_calendar-fast")).args(args).output().unwrap().status.code()
+    };
+
+    let dir = src.0.to_str().unwrap();
+    assert_eq!(exit_code(&[dir, "--order-by", "size"]), Some(2));
+    assert_eq!(exit_code(&[&format!("{}/missing", dir)]), Some(3));
+    assert_eq!(exit_code(&[dir, "--require-revdate", "--stdout"]), Some(4));
+}
+
+#[test]
+fn keep_going_skips_bad_documents() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= First\n:revdate: 2024-01-01\n");
+    src.write("b.adoc", b"= Bad\n:revdate: someday\n");
+    src.write("c.adoc", b"= Second\n:revdate: 2024-02-01\n");
+    let run_with = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_calendar-fast")).arg(&src.0).arg("--stdout").args(args).output().unwrap()
+    };
+
+    assert_eq!(run_with(&[]).status.code(), Some(4));
+
+    let output = run_with(&["--keep-going"]);
+    assert_eq!(output.status.code(), Some(7));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("b.adoc"));
+    assert_eq!(titles(&String::from_utf8(output.stdout).unwrap()), ["Second", "First"]);
+}
+
+#[test]
+fn output_inside_source_is_rejected() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= A\n:revdate: 2024-01-01\n");
+    src.write("sub/b.adoc", b"= B\n:revdate: 2024-01-02\n");
+    let exit_code = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_calendar-fast")).arg(&src.0).arg("--quiet").args(args).status().unwrap().code()
+    };
+
+    let out = src.0.join("sub/calendar.adoc");
+    let out = out.to_str().unwrap();
+    assert_eq!(exit_code(&["-o", out]), Some(2));
+    assert!(!Path::new(out).exists());
+
+    assert_eq!(exit_code(&["-o", out, "--allow-output-in-source"]), Some(0));
+    assert!(Path::new(out).exists());
+
+    let out = src.0.join("calendar.txt");
+    assert_eq!(exit_code(&["-o", out.to_str().unwrap()]), Some(0));
+}
+
+#[test]
+fn attributes_in_comment_blocks_are_ignored() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= Commented\n////\n:revdate: 2024-01-01\ninclude::other.adoc[]\n////\n\nText\n");
+
+    let docs = collect(&src.0, &collect_opts()).unwrap();
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].revdate, None);
+    assert_eq!(docs[0].content, "= Commented\n////\n:revdate: 2024-01-01\ninclude::other.adoc[]\n////\n\nText\n");
+}
+
+#[test]
+fn shorter_delimiters_dont_close_a_comment_block() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"//////\n////\n:revdate: 2020-01-01\n////\n//////\n= Nested\n:revdate: 2024-01-01\n");
+
+    let docs = collect(&src.0, &collect_opts()).unwrap();
+    assert_eq!(docs[0].title.as_deref(), Some("Nested"));
+    assert_eq!(docs[0].revdate, Some(Date { year: 2024, month: 1, day: 1 }));
+}
+
+#[test]
+fn unterminated_comment_block_keeps_the_document() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= Open\n:revdate: 2024-01-01\n\nText\n////\n:revdate: 2020-01-01\n");
+
+    let docs = collect(&src.0, &collect_opts()).unwrap();
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].revdate, Some(Date { year: 2024, month: 1, day: 1 }));
+    assert_eq!(docs[0].content, "= Open\n:revdate: 2024-01-01\n\nText\n////\n:revdate: 2020-01-01\n");
+}
+
+#[test]
+fn comment_paragraph_ends_at_blank_line() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"[comment]\n= Not the title\n:revdate: 2020-01-01\n\n= Title\n:revdate: 2024-01-01\n");
+    src.write("b.adoc", b"[comment]\n--\n= Not the title\n\n:revdate: 2020-01-01\n--\n= Title\n:revdate: 2023-01-01\n");
+
+    let docs = collect(&src.0, &collect_opts()).unwrap();
+    assert_eq!(docs[0].title.as_deref(), Some("Title"));
+    assert_eq!(docs[0].revdate, Some(Date { year: 2024, month: 1, day: 1 }));
+    assert_eq!(docs[1].title.as_deref(), Some("Title"));
+    assert_eq!(docs[1].revdate, Some(Date { year: 2023, month: 1, day: 1 }));
+}
+
+#[test]
+fn attribute_reference_revdate_is_undated() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= Dated\n:revdate: 2024-01-01\n");
+    src.write("b.adoc", b"= Reference\n:revdate: {docdate}\n\nText\n");
+
+    let docs = collect(&src.0, &collect_opts()).unwrap();
+    let doc = docs.iter().find(|doc| doc.title.as_deref() == Some("Reference")).unwrap();
+    assert_eq!(doc.revdate, None);
+    assert_eq!(titles(&run(&src.0, &[])), ["Dated", "Reference"]);
+}
+
+#[test]
+fn newest_per_dir() {
+    let src = TempDir::new();
+    src.write("p/old.adoc", b"= Old\n:revdate: 2024-01-01\n");
+    src.write("p/new.adoc", b"= New\n:revdate: 2024-03-01\n");
+    src.write("p/undated.adoc", b"= Undated in p\n");
+    src.write("q/a.adoc", b"= Tie A\n:revdate: 2023-05-05\n");
+    src.write("q/b.adoc", b"= Tie B\n:revdate: 2023-05-05\n");
+    src.write("r/undated.adoc", b"= Undated in r\n");
+
+    assert_eq!(titles(&run(&src.0, &["--newest-per-dir"])), ["New", "Tie A", "Undated in r"]);
+}
+
+#[test]
+fn empty_documents_are_skipped() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"");
+    src.write("b.adoc", b"  \n\t\n\n");
+    src.write("c.adoc", b":revdate: 2024-01-01\n\n// nothing yet\n");
+    src.write("d.adoc", b"= Title\n");
+
+    let docs = collect(&src.0, &collect_opts()).unwrap();
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].title.as_deref(), Some("Title"));
+}
+
+#[test]
+fn imagesdir_relative_to_base() {
+    let src = TempDir::new();
+    src.write("posts/a.adoc", b"= Post\n:revdate: 2024-01-01\n\nimage::cat.png[]\n");
+    src.write("pages/b.adoc", b"= Page\n:revdate: 2023-01-01\n:imagesdir: img\n");
+
+    let base = Some(src.0.clone());
+    let opts = CollectOptions { parse: ParseOptions { imagesdir_base: base.clone(), ..collect_opts().parse }, ..collect_opts() };
+    let mut docs = collect(&src.0, &opts).unwrap();
+    docs.sort_by_key(|doc| Reverse(doc.revdate));
+
+    let opts = GenerateOptions { imagesdir_base: base, ..generate_opts() };
+    assert_eq!(
+        render(&docs, &opts),
+        "= Calendar\n\n\n\n:leveloffset: +1\n\n\
+         :imagesdir: posts\n= Post\n:revdate: 2024-01-01\n\nimage::cat.png[]\n\n\
+         = Page\n:revdate: 2023-01-01\n:imagesdir: img\n:imagesdir: pages/img\n\n\
+         \n\n:leveloffset: -1\n\n",
+    );
+}
+
+#[test]
+fn one_blank_line_between_documents() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= First\n:revdate: 2024-02-01\n\nText one  \n\n\n\n");
+    src.write("b.adoc", b"= Second\n:revdate: 2024-01-01\r\n\r\nText two\r\n \r\n\r\n");
+
+    let mut docs = collect(&src.0, &collect_opts()).unwrap();
+    docs.sort_by_key(|doc| Reverse(doc.revdate));
+
+    let opts = GenerateOptions { force_imagesdir: Some(String::from("img")), ..generate_opts() };
+    assert_eq!(
+        render(&docs, &opts),
+        "= Calendar\n\n\n\n:leveloffset: +1\n\n\
+         :imagesdir: img\n= First\n:revdate: 2024-02-01\n\nText one  \n\n\
+         :imagesdir: img\n= Second\n:revdate: 2024-01-01\n\nText two\n\n\
+         \n\n:leveloffset: -1\n\n",
+    );
+}
+
+#[test]
+fn separator_between_documents() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= A\n:revdate: 2024-03-01\n");
+    src.write("b.adoc", b"= B\n:revdate: 2024-02-01\n");
+    src.write("c.adoc", b"= C\n:revdate: 2024-01-01\n");
+
+    let calendar = run(&src.0, &["--separator", "// ---8<---"]);
+    assert_eq!(calendar.matches("// ---8<---").count(), 2);
+    let parts: Vec<&str> = calendar.split("// ---8<---\n\n").collect();
+    assert_eq!(parts.len(), 3);
+    assert!(parts[0].ends_with("= A\n:revdate: 2024-03-01\n\n"));
+    assert!(parts[1].starts_with(":imagesdir: ") && parts[1].ends_with("= B\n:revdate: 2024-02-01\n\n"));
+    assert!(parts[2].starts_with(":imagesdir: ") && parts[2].contains("= C\n"));
+}
+
+#[test]
+fn relative_image_targets_are_rewritten() {
+    let src = TempDir::new();
+    src.write(
+        "posts/a.adoc",
+        b"= Post\n:revdate: 2024-01-01\n\nimage::cat.png[Cat]\n\
+          See image:icons/dog.png[] and image:/abs/x.png[], not myimage:y.png[].\n\
+          image::https://example.com/z.png[]\n\
+          :imagesdir: img\n\nimage::{base}/w.png[] image::v.png[]\n",
+    );
+
+    let opts = CollectOptions { parse: ParseOptions { rewrite_image_paths: true, ..collect_opts().parse }, ..collect_opts() };
+    let docs = collect(&src.0, &opts).unwrap();
+
+    let dir = src.0.join("posts").to_string_lossy().replace('\\', "/");
+    let expected = format!(
+        "= Post\n:revdate: 2024-01-01\n\nimage::{dir}/cat.png[Cat]\n\
+         See image:{dir}/icons/dog.png[] and image:/abs/x.png[], not myimage:y.png[].\n\
+         image::https://example.com/z.png[]\n\
+         :imagesdir: img\n:imagesdir: {dir}/img\n\nimage::{{base}}/w.png[] image::{dir}/img/v.png[]\n",
+    );
+    assert_eq!(docs[0].content, expected);
+}
+
+#[test]
+fn bom_is_stripped() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"\xEF\xBB\xBF= Title\n:revdate: 2024-01-01\n\nText \xEF\xBB\xBF\n");
+
+    let docs = collect(&src.0, &collect_opts()).unwrap();
+    assert_eq!(docs[0].title.as_deref(), Some("Title"));
+    assert_eq!(docs[0].revdate, Some(Date { year: 2024, month: 1, day: 1 }));
+    assert_eq!(docs[0].content, "= Title\n:revdate: 2024-01-01\n\nText \u{FEFF}\n");
+
+    let opts = CollectOptions { parse: ParseOptions { keep_bom: true, ..collect_opts().parse }, ..collect_opts() };
+    let docs = collect(&src.0, &opts).unwrap();
+    assert!(docs[0].content.starts_with("\u{FEFF}= Title\n"));
+}
+
+#[test]
+fn ranges_bracket_each_document() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= First\n:revdate: 2024-01-01\n\nText one\n");
+    src.write("b.adoc", b"= Second\n:revdate: 2025-02-01\n\nText two\n");
+
+    let docs = collect(&src.0, &collect_opts()).unwrap();
+    let opts = GenerateOptions {
+        source_comments: true,
+        group_by_year: true,
+        line_endings: LineEndings::Crlf,
+        ..generate_opts()
+    };
+    let mut out = Vec::new();
+    let ranges = generate_ranges(&mut out, &opts, docs.iter()).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    assert_eq!(ranges.len(), 2);
+    for range in &ranges {
+        let text = &out[range.start as usize..range.end as usize];
+        let title = range.doc.title.as_deref().unwrap();
+        assert!(text.starts_with("// source: "), "{text:?}");
+        assert!(text.contains("\r\n:imagesdir: "), "{text:?}");
+        assert!(text.contains(&format!("\r\n= {title}\r\n")), "{text:?}");
+        assert!(text.ends_with(&range.doc.content.trim_end().replace('\n', "\r\n")), "{text:?}");
+        assert!(out[range.end as usize..].starts_with("\r\n\r\n"));
+    }
+    assert!(ranges[0].end < ranges[1].start);
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn gzip_round_trip() {
+    use std::io::Read;
+
+    let src = TempDir::new();
+    src.write("a.adoc", b"= First\n:revdate: 2024-01-01\n\nText\n");
+    src.write("b.adoc", b"= Second\n:revdate: 2024-02-01\n");
+    let out = TempDir::new();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+        .current_dir(&out.0)
+        .arg(&src.0)
+        .args(["--gzip", "--quiet"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let mut decoded = String::new();
+    flate2::read::GzDecoder::new(fs::File::open(out.0.join("calendar.adoc.gz")).unwrap())
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, run(&src.0, &[]));
+}
+
+#[cfg(feature = "zip")]
+fn zip_archive(files: &[(&str, &[u8])]) -> std::io::Cursor<Vec<u8>> {
+    use std::io::{Cursor, Write};
+
+    use zip::write::SimpleFileOptions;
+
+    let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, contents) in files {
+        archive.start_file(*name, SimpleFileOptions::default()).unwrap();
+        archive.write_all(contents).unwrap();
+    }
+    let mut archive = archive.finish().unwrap();
+    archive.set_position(0);
+    archive
+}
+
+#[cfg(feature = "zip")]
+#[test]
+fn documents_from_a_zip_archive() {
+    use calendar_fast::{parse_entry, read_zip, FoundFiles};
+
+    let archive = zip_archive(&[
+        ("posts/a.adoc", b"= Post\n:revdate: 2024-02-01\n\nimage::cat.png[]\n"),
+        ("b.adoc", b"= Top\n:revdate: 2024-01-01\n"),
+        ("pages/c.adoc", b"= Page\n:revdate: 2023-01-01\n:imagesdir: img\n"),
+        ("notes.txt", b"= Not a document\n"),
+    ]);
+
+    let mut found = FoundFiles::default();
+    read_zip(archive, &collect_opts().traverse, &mut found).unwrap();
+    assert!(found.files.is_empty());
+    let mut docs: Vec<Doc> = found.entries.iter().filter_map(|entry| parse_entry(entry, &collect_opts().parse).unwrap()).collect();
+    docs.sort_by_key(|doc| Reverse(doc.revdate));
+
+    assert_eq!(
+        render(&docs, &generate_opts()),
+        "= Calendar\n\n\n\n:leveloffset: +1\n\n\
+         :imagesdir: posts\n= Post\n:revdate: 2024-02-01\n\nimage::cat.png[]\n\n\
+         :imagesdir: .\n= Top\n:revdate: 2024-01-01\n\n\
+         = Page\n:revdate: 2023-01-01\n:imagesdir: img\n:imagesdir: pages/img\n\n\
+         \n\n:leveloffset: -1\n\n",
+    );
+}
+
+#[cfg(feature = "zip")]
+#[test]
+fn reports_read_archived_documents() {
+    let dir = TempDir::new();
+    let archive = zip_archive(&[
+        ("a.adoc", b"= Same\n:revdate: 2024-01-01\n"),
+        ("b.adoc", b"= Same\n:revdate: 2024-01-01\n"),
+        ("c.adoc", b"= Other\n"),
+    ]);
+    dir.write("src.zip", &archive.into_inner());
+    // Same name as an entry, so reading the entry from disk would find this.
+    dir.write("a.adoc", b"= Decoy\n");
+    let stdout = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+            .current_dir(&dir.0)
+            .args(["src.zip", "--config", "/dev/null", "-o", "out.adoc"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert!(stdout(&["--report-duplicates"]).contains("Duplicate group 1:\n  a.adoc\n  b.adoc\n"));
+    assert!(stdout(&["--only-changed", "cache"]).contains("Documents    changed: 3.\n"));
+    assert!(stdout(&["--only-changed", "cache"]).contains("Documents    changed: 0.\n"));
+}
+
+#[test]
+fn html_index_links_to_document_anchors() {
+    let src = TempDir::new();
+    src.write("posts/a.adoc", b"= Fish & <Chips>\n:revdate: 2024-06-01\n");
+    src.write("b.adoc", b"= \"Quoted\"\n");
+    let index = src.0.with_extension("html");
+
+    // The slugs are relative to the working directory.
+    let output = Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+        .current_dir(&src.0)
+        .args([".", "--stdout", "--quiet", "--html-index", index.to_str().unwrap(), "--base-url", "cal.html?a=1&b=2"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let calendar = String::from_utf8(output.stdout).unwrap();
+    let html = fs::read_to_string(&index).unwrap();
+    fs::remove_file(&index).unwrap();
+    assert!(!index.with_extension("html.tmp").exists());
+
+    assert_eq!(
+        html,
+        "<ul>\n\
+         <li>2024-06-01 <a href=\"cal.html?a=1&amp;b=2#doc-posts-a\">Fish &amp; &lt;Chips&gt;</a></li>\n\
+         <li><a href=\"cal.html?a=1&amp;b=2#doc-b\">&quot;Quoted&quot;</a></li>\n\
+         </ul>\n",
+    );
+    assert!(calendar.contains("[[doc-posts-a]]\n= Fish & <Chips>\n"));
+    assert!(calendar.contains("[[doc-b]]\n= \"Quoted\"\n"));
+}
+
+#[test]
+fn default_level_offset_follows_the_header() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= A\n:revdate: 2024-06-01\n");
+    let header = src.0.with_extension("header");
+    fs::write(&header, "= Custom\n\n").unwrap();
+    let header_arg = header.to_str().unwrap();
+
+    for (args, offset) in [
+        (&["--header-level", "0"][..], ":leveloffset: +1\n"),
+        (&["--header-level", "1"], ":leveloffset: +2\n"),
+        (&["--header-level", "2"], ":leveloffset: +3\n"),
+        (&["--header", header_arg], ":leveloffset: +1\n"),
+        (&["--header", header_arg, "--header-level", "2"], ":leveloffset: +1\n"),
+    ] {
+        assert!(run(&src.0, args).contains(offset), "{:?}", args);
+    }
+    fs::remove_file(&header).unwrap();
+}
+
+#[test]
+fn group_headings_nest_below_the_calendar_title() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= A\n:revdate: 2024-06-01\n");
+    src.write("b.adoc", b"= B\n:revdate: 2024-05-01\n");
+    src.write("c.adoc", b"= C\n");
+
+    let calendar = run(&src.0, &["--level-offset", "0", "--group-by-year", "--group-by-month"]);
+    let structure: Vec<&str> =
+        calendar.lines().filter(|line| line.starts_with('=') || line.starts_with(":leveloffset:")).collect();
+    assert_eq!(
+        structure,
+        [
+            "= Calendar",
+            ":leveloffset: +0",
+            "== 2024",
+            "=== June 2024",
+            ":leveloffset: +3",
+            "= A",
+            ":leveloffset: -3",
+            "=== May 2024",
+            ":leveloffset: +3",
+            "= B",
+            ":leveloffset: -3",
+            "== Undated",
+            ":leveloffset: +2",
+            "= C",
+            ":leveloffset: -2",
+            ":leveloffset: +0",
+        ],
+    );
+}
+
+#[test]
+fn flat_groups_have_no_leveloffsets() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= A\n:revdate: 2024-06-01\n");
+    src.write("b.adoc", b"= B\n:revdate: 2023-05-01\n");
+
+    let calendar = run(&src.0, &["--flat", "--group-by-year"]);
+    assert!(!calendar.contains(":leveloffset:"), "{}", calendar);
+    let headings: Vec<&str> = calendar.lines().filter(|line| line.starts_with('=')).collect();
+    assert_eq!(headings, ["= Calendar", "== 2024", "= A", "== 2023", "= B"]);
+}
+
+#[test]
+fn duplicate_revdates_compare_the_time() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= Morning\n:revdate: 2024-06-01 09:00\n");
+    src.write("b.adoc", b"= Evening\n:revdate: 2024-06-01 18:30\n");
+    let duplicates = |src: &TempDir| {
+        let docs = collect(&src.0, &collect_opts()).unwrap();
+        let docs: Vec<&Doc> = docs.iter().collect();
+        duplicate_revdates(&docs)
+            .into_iter()
+            .map(|(date, time, a, b)| (date.to_string(), time, a.to_string(), b.to_string()))
+            .collect::<Vec<_>>()
+    };
+    let strict = |src: &TempDir| {
+        Command::new(env!("CARGO_BIN_EXE_calendar-fast")).arg(&src.0).args(["--stdout", "--quiet", "--strict"]).output().unwrap()
+    };
+
+    assert!(duplicates(&src).is_empty());
+    assert!(strict(&src).status.success());
+
+    let c = src.write("c.adoc", b"= Also evening\n:revdate: 2024-06-01 18:30\n");
+    let b = src.0.join("b.adoc");
+    assert_eq!(
+        duplicates(&src),
+        [(String::from("2024-06-01"), Some(Time { hour: 18, minute: 30 }), b.display().to_string(), c.display().to_string())],
+    );
+    let output = strict(&src);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Duplicate revdate 2024-06-01 18:30 in "));
+}
+
+#[cfg(unix)]
+#[test]
+fn strict_paths_rejects_non_utf8_paths() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let src = TempDir::new();
+    src.write("a.adoc", b"= A\n");
+    fs::write(src.0.join(OsStr::from_bytes(b"caf\xe9.adoc")), "= B\n").unwrap();
+    let exit_code = |args: &[&str]| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_calendar-fast"));
+        command.arg(&src.0).args(["--stdout", "--quiet"]).args(args).status().unwrap().code()
+    };
+
+    assert_eq!(exit_code(&[]), Some(0));
+    assert_eq!(exit_code(&["--strict-paths"]), Some(3));
+}
+
+#[test]
+fn append_adds_balanced_blocks() {
+    let dir = TempDir::new();
+    dir.write("one/a.adoc", b"= One\n:revdate: 2024-01-01\n");
+    dir.write("two/a.adoc", b"= Two\n:revdate: 2024-02-01\n");
+    dir.write("three/a.adoc", b"= Three\n");
+    let footer = dir.write("footer.adoc", b"// footer\n");
+    let out = dir.0.join("calendar.adoc");
+    let write = |src: &str, args: &[&str]| {
+        let status = Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+            .arg(dir.0.join(src))
+            .args(["--quiet", "--footer", footer.to_str().unwrap(), "-o", out.to_str().unwrap()])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+
+    write("one", &["--toc"]);
+    write("two", &["--append"]);
+    write("three", &["--append"]);
+    let calendar = fs::read_to_string(&out).unwrap();
+
+    assert_eq!(calendar.matches("= Calendar\n").count(), 1);
+    assert_eq!(calendar.matches("* <<doc-").count(), 1);
+    assert_eq!(calendar.matches("// footer\n").count(), 1);
+    let headings: Vec<&str> = calendar.lines().filter(|line| line.starts_with('=')).collect();
+    assert_eq!(headings, ["= Calendar", "= One", "= Two", "= Three"]);
+
+    let mut offset = 0;
+    for line in calendar.lines() {
+        if let Some(change) = line.strip_prefix(":leveloffset: ") {
+            offset += change.parse::<i32>().unwrap();
+            assert!(offset == 0 || offset == 1, "{}", calendar);
+        }
+    }
+    assert_eq!(offset, 0);
+}
+
+#[test]
+fn list_authors_counts_documents() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= A\nAda Lovelace\n:revdate: 2024-01-01\n");
+    src.write("b.adoc", b"= B\n:author: Ada Lovelace\n");
+    src.write("c.adoc", b"= C\n:author: Grace Hopper\n");
+    src.write("d.adoc", b"= No author\n");
+
+    assert_eq!(run(&src.0, &["--list-authors"]), "    2  Ada Lovelace\n    1  Grace Hopper\n");
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn path_regex_filters() {
+    let src = TempDir::new();
+    src.write("posts/2024/a.adoc", b"= A\n:revdate: 2024-03-01\n");
+    src.write("posts/2023/b.adoc", b"= B\n:revdate: 2023-03-01\n");
+    src.write("drafts/c.adoc", b"= C\n:revdate: 2024-01-01\n");
+
+    assert_eq!(titles(&run(&src.0, &["--include-path-regex", "/posts/"])), ["A", "B"]);
+    assert_eq!(titles(&run(&src.0, &["--exclude-path-regex", r"/20\d\d/"])), ["C"]);
+    assert_eq!(titles(&run(&src.0, &["--include-path-regex", "/posts/", "--exclude-path-regex", "2023"])), ["A"]);
+}
+
+#[test]
+fn missing_title_policies() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= Zebra\n");
+    src.write("b.adoc", b"= Apple\n");
+    src.write("Mango.adoc", b":revdate: 2024-01-01\n\nNo title.\n");
+
+    let order = |policy: &str| {
+        let calendar = run(&src.0, &["--order-by", "title", "--missing-title", policy, "--source-comments"]);
+        calendar
+            .lines()
+            .filter_map(|line| line.strip_prefix("// source: "))
+            .map(|path| path.rsplit('/').next().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(order("first"), ["Mango.adoc", "b.adoc", "a.adoc"]);
+    assert_eq!(order("last"), ["b.adoc", "a.adoc", "Mango.adoc"]);
+    assert_eq!(order("filename"), ["b.adoc", "Mango.adoc", "a.adoc"]);
+}
+
+#[test]
+fn report_duplicates_lists_identical_files() {
+    let src = TempDir::new();
+    let a = src.write("a.adoc", b"= Copy\n:revdate: 2024-01-01\n");
+    let b = src.write("sub/b.adoc", b"= Copy\n:revdate: 2024-01-01\n");
+    src.write("c.adoc", b"= Unique\n:revdate: 2024-01-01\n");
+
+    assert_eq!(
+        run(&src.0, &["--report-duplicates"]),
+        format!("Duplicate group 1:\n  {}\n  {}\n", a.display(), b.display()),
+    );
+}
+
+#[test]
+fn check_monotonic_warns_about_out_of_order_entries() {
+    let src = TempDir::new();
+    let prev = src.write("log/01.adoc", b"= Newest\n:revdate: 2024-03-01\n");
+    let future = src.write("log/02.adoc", b"= Future\n:revdate: 2025-01-01\n");
+    src.write("log/03.adoc", b"= Oldest\n:revdate: 2024-01-01\n");
+    src.write("other/01.adoc", b"= Fine\n:revdate: 2023-01-01\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+        .arg(&src.0)
+        .args(["--stdout", "--check-monotonic"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let warnings: Vec<String> = String::from_utf8(output.stderr)
+        .unwrap()
+        .lines()
+        .filter(|line| line.contains("is newer than"))
+        .map(String::from)
+        .collect();
+    assert_eq!(
+        warnings,
+        [format!("Warning: {}: revdate 2025-01-01 is newer than the preceding {} (2024-03-01).", future.display(), prev.display())],
+    );
+}
+
+#[test]
+fn force_imagesdir_replaces_the_documents_own() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= A\n:revdate: 2024-02-01\n:imagesdir: pics\n\nimage::cat.png[]\n");
+    src.write("b/b.adoc", b"= B\n:revdate: 2024-01-01\n");
+
+    let calendar = run(&src.0, &["--force-imagesdir", "/srv/images"]);
+    assert_eq!(calendar.matches(":imagesdir: /srv/images\n").count(), 2);
+    assert_eq!(calendar.matches(":imagesdir:").count(), 2);
+    assert!(calendar.contains(":imagesdir: /srv/images\n= A\n:revdate: 2024-02-01\n\nimage::cat.png[]\n"));
+}
+
+#[test]
+fn report_unknown_attributes_lists_typos() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= A\n:revdat: 2024-01-01\n:imagesdir: img\n");
+    src.write("b.adoc", b"= B\n:revdat: 2024-02-01\n:toc:\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+        .arg(&src.0)
+        .args(["--stdout", "--report-unknown-attributes"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Unknown attributes: 2.\n    2  :revdat:\n    1  :toc:\n"), "{}", stderr);
+}
+
+#[test]
+fn annotate_mtime_writes_the_modification_time() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let src = TempDir::new();
+    let path = src.write("a.adoc", b"= A\n:revdate: 2025-06-01\n");
+    let file = fs::File::options().write(true).open(&path).unwrap();
+    file.set_modified(UNIX_EPOCH + Duration::from_secs(1748781000)).unwrap();
+    drop(file);
+
+    let calendar = run(&src.0, &["--annotate-mtime"]);
+    assert_eq!(calendar.matches("// mtime: ").count(), 1);
+    assert!(calendar.contains("// mtime: 2025-06-01T12:30:00Z\n"), "{}", calendar);
+    assert!(!run(&src.0, &[]).contains("// mtime: "));
+}
+
+#[test]
+fn strip_leading_empty_only_touches_the_first_document() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"\n\n= A\n:revdate: 2024-02-01\n");
+    src.write("b.adoc", b"\n\n= B\n:revdate: 2024-01-01\n");
+
+    let calendar = run(&src.0, &["--strip-leading-empty", "--force-imagesdir", "img"]);
+    assert!(calendar.contains(":imagesdir: img\n= A\n:revdate: 2024-02-01\n\n:imagesdir: img\n\n\n= B\n"), "{}", calendar);
+    assert!(run(&src.0, &["--force-imagesdir", "img"]).contains(":imagesdir: img\n\n\n= A\n"));
+}
+
+#[test]
+fn fail_if_changed_only_writes_changes() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let dir = TempDir::new();
+    dir.write("src/a.adoc", b"= A\n:revdate: 2024-01-01\n");
+    let out = dir.0.join("calendar.adoc");
+    let exit_code = || {
+        Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+            .arg(dir.0.join("src"))
+            .args(["--quiet", "--fail-if-changed", "-o", out.to_str().unwrap()])
+            .status()
+            .unwrap()
+            .code()
+    };
+
+    assert_eq!(exit_code(), Some(6));
+    let written = fs::read_to_string(&out).unwrap();
+    assert!(written.contains("= A\n"));
+
+    let old = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    fs::File::options().write(true).open(&out).unwrap().set_modified(old).unwrap();
+    assert_eq!(exit_code(), Some(0));
+    assert_eq!(fs::metadata(&out).unwrap().modified().unwrap(), old);
+
+    dir.write("src/b.adoc", b"= B\n:revdate: 2024-02-01\n");
+    assert_eq!(exit_code(), Some(6));
+    assert!(fs::read_to_string(&out).unwrap().contains("= B\n"));
+    assert_ne!(fs::metadata(&out).unwrap().modified().unwrap(), old);
+}
+
+#[test]
+fn split_dir_by_quarter() {
+    let dir = TempDir::new();
+    dir.write("src/a.adoc", b"= A\n:revdate: 2024-02-01\n");
+    dir.write("src/b.adoc", b"= B\n:revdate: 2024-05-01\n");
+    dir.write("src/c.adoc", b"= C\n:revdate: 2024-03-31\n");
+    dir.write("src/d.adoc", b"= D\n:revdate: 2024-11-01\n");
+    let split = |out: &str, args: &[&str]| {
+        let out = dir.0.join(out);
+        let status = Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+            .arg(dir.0.join("src"))
+            .args(["--quiet", "--bucket", "quarterly", "--split-dir", out.to_str().unwrap()])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let mut names: Vec<String> =
+            fs::read_dir(&out).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+        names.sort();
+        (out, names)
+    };
+
+    let (out, names) = split("out", &[]);
+    assert_eq!(names, ["2024-Q1.adoc", "2024-Q2.adoc", "2024-Q4.adoc"]);
+    assert_eq!(titles(&fs::read_to_string(out.join("2024-Q1.adoc")).unwrap()), ["C", "A"]);
+    assert_eq!(titles(&fs::read_to_string(out.join("2024-Q2.adoc")).unwrap()), ["B"]);
+    assert_eq!(titles(&fs::read_to_string(out.join("2024-Q4.adoc")).unwrap()), ["D"]);
+
+    let (_, names) = split("all", &["--emit-empty-groups"]);
+    assert_eq!(names, ["2024-Q1.adoc", "2024-Q2.adoc", "2024-Q3.adoc", "2024-Q4.adoc"]);
+}
+
+#[test]
+fn title_from_filename_after_the_delimiter() {
+    let src = TempDir::new();
+    src.write("2025-06-01__my-first_post.adoc", b":revdate: 2025-06-01\n\nText.\n");
+    src.write("2025-05-01__titled.adoc", b"= Own title\n:revdate: 2025-05-01\n");
+
+    let json = run(&src.0, &["--format", "json", "--title-from-filename", "--filename-title-delimiter", "__"]);
+    assert!(json.contains("\"title\": \"my first post\""), "{}", json);
+    assert!(json.contains("\"title\": \"Own title\""));
+
+    let json = run(&src.0, &["--format", "json", "--title-from-filename"]);
+    assert!(json.contains("\"title\": \"2025 06 01 my first post\""), "{}", json);
+}
+
+#[test]
+fn title_after_an_attribute_block() {
+    let src = TempDir::new();
+    src.write("a.adoc", b":toc:\n:icons: font\n= The Title\n:revdate: 2024-01-01\n\n== Section\n");
+    src.write("b.adoc", b"////\n= Commented out\n////\n= Real title\n");
+    src.write("c.adoc", b"Just text.\n\n== Section only\n");
+
+    let docs = collect(&src.0, &collect_opts()).unwrap();
+    let title = |name: &str| docs.iter().find(|doc| doc.path.ends_with(name)).unwrap().title.clone();
+    assert_eq!(title("a.adoc").as_deref(), Some("The Title"));
+    assert_eq!(title("b.adoc").as_deref(), Some("Real title"));
+    assert_eq!(title("c.adoc"), None);
+}
+
+#[test]
+fn date_anchors_are_unique() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= A\n:revdate: 2024-06-01\n");
+    src.write("b.adoc", b"= B\n:revdate: 2024-06-01\n");
+    src.write("c.adoc", b"= C\n:revdate: 2024-06-01\n");
+    src.write("d.adoc", b"= D\n:revdate: 2024-05-31\n");
+    src.write("e.adoc", b"= Undated\n");
+
+    let calendar = run(&src.0, &["--date-anchors", "--force-imagesdir", "img"]);
+    let anchors: Vec<&str> = calendar.lines().filter(|line| line.starts_with("[[")).collect();
+    assert_eq!(anchors, ["[[date-2024-06-01]]", "[[date-2024-06-01-2]]", "[[date-2024-06-01-3]]", "[[date-2024-05-31]]"]);
+    assert!(calendar.contains("[[date-2024-06-01-2]]\n= B\n"));
+}
+
+#[test]
+fn inline_option_values() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= A\n:revdate: 2024-06-01\n");
+    let output = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_calendar-fast")).arg(&src.0).args(["--stdout"]).args(args).output().unwrap()
+    };
+
+    assert!(run(&src.0, &["--title=My Log"]).starts_with("= My Log\n"));
+    assert_eq!(run(&src.0, &["--title=My Log"]), run(&src.0, &["--title", "My Log"]));
+
+    let empty = output(&["--header="]);
+    assert_eq!(empty.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8(empty.stderr).unwrap(),
+        "Error: You typed --header, but didn't specify what the file is afterwards.\n",
+    );
+    assert_eq!(output(&["--imglink=yes"]).status.code(), Some(2));
+}
+
+#[test]
+fn only_changed_reports_edits_between_runs() {
+    let dir = TempDir::new();
+    let a = dir.write("src/a.adoc", b"= A\n:revdate: 2024-01-01\n");
+    let b = dir.write("src/b.adoc", b"= B\n:revdate: 2024-02-01\n");
+    let changed = || {
+        let output = Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+            .arg(dir.0.join("src"))
+            .args(["--only-changed", dir.0.join("cache").to_str().unwrap(), "-o", dir.0.join("out.adoc").to_str().unwrap()])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let (_, list) = stdout.split_once("Documents    changed: ").unwrap();
+        list.lines().take_while(|line| !line.starts_with("Documents")).map(String::from).collect::<Vec<_>>()
+    };
+
+    assert_eq!(changed(), [String::from("2."), format!("  {}", a.display()), format!("  {}", b.display())]);
+    assert_eq!(changed(), ["0."]);
+    fs::write(&b, "= B\n:revdate: 2024-02-02\n").unwrap();
+    assert_eq!(changed(), [String::from("1."), format!("  {}", b.display())]);
+    assert!(fs::read_to_string(dir.0.join("out.adoc")).unwrap().contains("= A\n"));
+}
+
+#[test]
+fn strict_revdate_position_warns_about_body_revdates() {
+    let src = TempDir::new();
+    let late = src.write("a.adoc", b"= Late\n\nSome text.\n\n:revdate: 2024-01-01\n");
+    src.write("b.adoc", b"= Dated\n:revdate: 2023-01-01\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+        .arg(&src.0)
+        .args(["--stdout", "--strict-revdate-position"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(titles(&String::from_utf8(output.stdout).unwrap()), ["Dated", "Late"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!("Warning: {}:5: :revdate: outside of the document header is ignored.\n", late.display())));
+    assert_eq!(stderr.matches("outside of the document header").count(), 1);
+}
+
+#[test]
+fn max_depth_limits_recursion() {
+    let src = TempDir::new();
+    src.write("top.adoc", b"= Top\n:revdate: 2024-04-01\n");
+    src.write("one/a.adoc", b"= One\n:revdate: 2024-03-01\n");
+    src.write("one/two/b.adoc", b"= Two\n:revdate: 2024-02-01\n");
+    src.write("one/two/three/c.adoc", b"= Three\n:revdate: 2024-01-01\n");
+
+    assert_eq!(titles(&run(&src.0, &[])), ["Top", "One", "Two", "Three"]);
+    assert_eq!(titles(&run(&src.0, &["--max-depth", "0"])), ["Top"]);
+    assert_eq!(titles(&run(&src.0, &["--max-depth", "1"])), ["Top", "One"]);
+    assert_eq!(titles(&run(&src.0, &["--max-depth", "2"])), ["Top", "One", "Two"]);
+}
+
+#[test]
+fn all_default_extensions_are_collected() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= Adoc\n:revdate: 2024-04-01\n");
+    src.write("b.asciidoc", b"= Asciidoc\n:revdate: 2024-03-01\n");
+    src.write("c.asc", b"= Asc\n:revdate: 2024-02-01\n");
+    src.write("d.AD", b"= Ad\n:revdate: 2024-01-01\n");
+    src.write("README", b"= No extension\n");
+    src.write("notes.txt", b"= Text\n");
+
+    assert_eq!(titles(&run(&src.0, &[])), ["Adoc", "Asciidoc", "Asc", "Ad"]);
+    assert_eq!(titles(&run(&src.0, &["--ext", "txt", "--ext", "asc"])), ["Asc", "Text"]);
+}
+
+#[test]
+fn attribute_values_after_any_whitespace() {
+    let src = TempDir::new();
+    src.write("tab.adoc", b"= Tab\n:revdate:\t2024-01-02\n:author:\tAda\n:imagesdir:\timg\n");
+    src.write("spaces.adoc", b"= Spaces\n:revdate:  2024-01-03\n:author:   Grace\n:imagesdir:  pics\n");
+
+    let docs = collect(&src.0, &collect_opts()).unwrap();
+    let doc = |title: &str| docs.iter().find(|doc| doc.title.as_deref() == Some(title)).unwrap();
+    assert_eq!(doc("Tab").revdate, Some(Date { year: 2024, month: 1, day: 2 }));
+    assert_eq!(doc("Tab").author.as_deref(), Some("Ada"));
+    assert!(doc("Tab").has_imagesdir);
+    assert_eq!(doc("Spaces").revdate, Some(Date { year: 2024, month: 1, day: 3 }));
+    assert_eq!(doc("Spaces").author.as_deref(), Some("Grace"));
+    assert!(doc("Spaces").has_imagesdir);
+}
+
+#[test]
+fn ties_are_broken_by_path_whatever_the_input_order() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let src = TempDir::new();
+    let files = [
+        src.write("c.adoc", b"[#same]\n= Same\n:revdate: 2024-06-01\n"),
+        src.write("a.adoc", b"[#same]\n= Same\n:revdate: 2024-06-01\n"),
+        src.write("b.adoc", b"[#same]\n= Same\n:revdate: 2024-06-01\n"),
+    ];
+    let listed = |order: [usize; 3], args: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+            .args(["-", "--stdout", "--quiet", "--source-comments"])
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let list: String = order.iter().map(|&i| format!("{}\n", files[i].display())).collect();
+        child.stdin.take().unwrap().write_all(list.as_bytes()).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    for args in [&[][..], &["--order-by", "title"], &["--order-by", "id"]] {
+        let calendar = listed([0, 1, 2], args);
+        assert_eq!(calendar, listed([2, 1, 0], args));
+        assert_eq!(calendar, listed([1, 0, 2], args));
+        let sources: Vec<&str> = calendar.lines().filter(|line| line.starts_with("// source: ")).collect();
+        assert!(sources[0].ends_with("/a.adoc") && sources[1].ends_with("/b.adoc") && sources[2].ends_with("/c.adoc"));
+    }
+}
+
+#[test]
+fn excluded_files_are_never_parsed() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= Kept\n:revdate: 2024-01-01\n");
+    // Would fail the run if it were parsed.
+    src.write("draft-b.adoc", b"= Draft\n:revdate: someday\n");
+    src.write("drafts/c.adoc", b"= Nested draft\n:revdate: 2024-02-01\n");
+
+    assert_eq!(titles(&run(&src.0, &["--exclude", "draft-*", "--exclude", "*/drafts/*"])), ["Kept"]);
+    assert_eq!(titles(&run(&src.0, &["--exclude", "draft-?.adoc"])), ["Nested draft", "Kept"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn documents_reached_twice_are_collected_once() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let src = TempDir::new();
+    let real = src.write("real/a.adoc", b"= A\n:revdate: 2024-01-01\n");
+    std::os::unix::fs::symlink(src.0.join("real"), src.0.join("via-link")).unwrap();
+    let sources = |calendar: &str| {
+        calendar.lines().filter_map(|line| line.strip_prefix("// source: ")).map(String::from).collect::<Vec<_>>()
+    };
+
+    // The first path the file is found under is the one that's kept.
+    let calendar = run(&src.0, &["--source-comments"]);
+    assert_eq!(sources(&calendar), [src.0.join("real/a.adoc").display().to_string()]);
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+        .args(["-", "--stdout", "--quiet", "--source-comments"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let linked = src.0.join("via-link/a.adoc");
+    let list = format!("{}\n{}\n{}\n", linked.display(), real.display(), linked.display());
+    child.stdin.take().unwrap().write_all(list.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(sources(&String::from_utf8(output.stdout).unwrap()), [linked.display().to_string()]);
+}
+
+#[test]
+fn author_from_attribute_or_author_line() {
+    let src = TempDir::new();
+    src.write("attribute.adoc", b"= Attribute\n:author: Ada Lovelace\n");
+    src.write("byline.adoc", b"= Byline\nGrace Hopper <grace@example.com>; Alan Turing\n:revdate: 2024-01-01\n");
+    src.write("both.adoc", b"= Both\nGrace Hopper\n:author: Ada Lovelace\n");
+    src.write("none.adoc", b"= None\n\nJust text.\n");
+
+    let docs = collect(&src.0, &collect_opts()).unwrap();
+    let author = |title: &str| docs.iter().find(|doc| doc.title.as_deref() == Some(title)).unwrap().author.clone();
+    assert_eq!(author("Attribute").as_deref(), Some("Ada Lovelace"));
+    assert_eq!(author("Byline").as_deref(), Some("Grace Hopper"));
+    assert_eq!(author("Both").as_deref(), Some("Ada Lovelace"));
+    assert_eq!(author("None"), None);
+    assert!(run(&src.0, &["--format", "json"]).contains("\"title\": \"Byline\", \"author\": \"Grace Hopper\""));
+}
+
+#[test]
+fn revdate_and_title_only_count_in_the_header() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= Header title\n:author: Ada\n\nBody text.\n\n:revdate: 2024-01-01\n\n= Body title\n");
+    src.write("b.adoc", b"Intro paragraph.\n\n= Late title\n:revdate: 2024-02-01\n");
+
+    let docs = collect(&src.0, &collect_opts()).unwrap();
+    let doc = |name: &str| docs.iter().find(|doc| doc.path.ends_with(name)).unwrap();
+    assert_eq!(doc("a.adoc").title.as_deref(), Some("Header title"));
+    assert_eq!(doc("a.adoc").revdate, None);
+    assert_eq!(doc("b.adoc").title, None);
+    assert_eq!(doc("b.adoc").revdate, None);
+}
+
+#[test]
+fn tag_filters() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= Rust\n:revdate: 2024-04-01\n:tags: rust, release\n");
+    src.write("b.adoc", b"= Go\n:revdate: 2024-03-01\n:tags: go\n");
+    src.write("c.adoc", b"= Both\n:revdate: 2024-02-01\n:tags: go,rust\n");
+    src.write("d.adoc", b"= Untagged\n:revdate: 2024-01-01\n");
+
+    assert_eq!(titles(&run(&src.0, &[])), ["Rust", "Go", "Both", "Untagged"]);
+    assert_eq!(titles(&run(&src.0, &["--tag", "rust"])), ["Rust", "Both"]);
+    assert_eq!(titles(&run(&src.0, &["--tag", "release"])), ["Rust"]);
+    assert_eq!(titles(&run(&src.0, &["--tag", "go", "--tag", "release"])), ["Rust", "Go", "Both"]);
+    assert!(titles(&run(&src.0, &["--tag", "python"])).is_empty());
+}
+
+#[test]
+fn url_imagesdirs_are_passed_through() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= Remote\n:revdate: 2024-02-01\n:imagesdir: https://example.com/img\n");
+    src.write("b.adoc", b"= Plain\n:revdate: 2024-01-01\n:imagesdir: http://example.com/img\n");
+
+    let calendar = run(&src.0, &[]);
+    assert_eq!(calendar.matches(":imagesdir:").count(), 2, "{}", calendar);
+    assert!(calendar.contains("= Remote\n:revdate: 2024-02-01\n:imagesdir: https://example.com/img\n\n"));
+    assert!(calendar.contains("= Plain\n:revdate: 2024-01-01\n:imagesdir: http://example.com/img\n\n"));
+}
+
+#[test]
+fn anchors_get_suffixes_for_repeated_slugs() {
+    let src = TempDir::new();
+    src.write("a b.adoc", b"= One\n:revdate: 2024-03-01\n");
+    src.write("a-b.adoc", b"= Two\n:revdate: 2024-02-01\n");
+    src.write("A_B.adoc", b"= Three\n:revdate: 2024-01-01\n");
+    src.write("posts/x.adoc", b"= Four\n");
+
+    // The slugs are relative to the working directory.
+    let output = Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+        .current_dir(&src.0)
+        .args([".", "--stdout", "--quiet", "--anchors"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let calendar = String::from_utf8(output.stdout).unwrap();
+    let anchors: Vec<&str> = calendar.lines().filter(|line| line.starts_with("[[")).collect();
+    assert_eq!(anchors, ["[[doc-a-b]]", "[[doc-a-b-2]]", "[[doc-a-b-3]]", "[[doc-posts-x]]"]);
+    assert!(calendar.contains("[[doc-a-b-2]]\n= Two\n"));
+}
+
+#[test]
+fn runs_are_reproducible() {
+    let src = TempDir::new();
+    for name in ["z/9.adoc", "m/5.adoc", "a/1.adoc", "m/b/3.adoc", "q.adoc", "c.adoc", "b.adoc"] {
+        src.write(name, format!("= {}\n:revdate: 2024-06-01\n", name).as_bytes());
+    }
+    src.write("a/undated.adoc", b"= Undated\n");
+    let output = || {
+        let output = Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+            .arg(&src.0)
+            .args(["--stdout", "--verbose", "--source-comments"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stderr: String = String::from_utf8(output.stderr)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.contains(" time: "))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        (output.stdout, stderr)
+    };
+
+    let first = output();
+    assert_eq!(first, output());
+    assert_eq!(first, output());
+}
+
+#[test]
+fn undated_documents_with_and_without_bounds() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= Dated\n:revdate: 2024-06-01\n");
+    src.write("b.adoc", b"= Undated\n");
+
+    assert_eq!(titles(&run(&src.0, &[])), ["Dated", "Undated"]);
+    assert_eq!(titles(&run(&src.0, &["--include-undated"])), ["Dated", "Undated"]);
+    assert_eq!(titles(&run(&src.0, &["--exclude-undated"])), ["Dated"]);
+    for bound in [&["--start-date", "2024-01-01"][..], &["--end-date", "2024-12-31"], &["--since", "10000d"]] {
+        assert_eq!(titles(&run(&src.0, bound)), ["Dated"]);
+        assert_eq!(titles(&run(&src.0, &[bound, &["--include-undated"]].concat())), ["Dated", "Undated"]);
+        assert_eq!(titles(&run(&src.0, &[bound, &["--exclude-undated"]].concat())), ["Dated"]);
+    }
+    // The last of the two flags wins.
+    assert_eq!(titles(&run(&src.0, &["--include-undated", "--exclude-undated"])), ["Dated"]);
+    assert_eq!(titles(&run(&src.0, &["--exclude-undated", "--include-undated"])), ["Dated", "Undated"]);
+}
+
+#[test]
+fn empty_imagesdirs_with_imglink() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= A\n:revdate: 2024-01-01\n:imagesdir:\n\nimage::cat.png[]\n");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+        .arg(&src.0)
+        .args(["--stdout", "--quiet", "--imglink", "--force-imagesdir", ""])
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(2));
+    assert!(run(&src.0, &["--imglink"]).contains("\nlink:cat.png[]\n"));
+    assert!(run(&src.0, &["--imglink", "--force-imagesdir", "img/"]).contains("\nlink:img/cat.png[]\n"));
+}
+
+#[test]
+fn month_headings_without_all_month_names() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= A\n:revdate: 2024-06-01\n");
+    let docs = collect(&src.0, &collect_opts()).unwrap();
+
+    let mut opts = generate_opts();
+    opts.group_by_month = true;
+    opts.month_names = vec![String::from("Janvier")];
+    assert!(render(&docs, &opts).contains("\n= June 2024\n"));
+    opts.month_names = Vec::new();
+    assert!(render(&docs, &opts).contains("\n= June 2024\n"));
+}
+
+#[test]
+fn quiet_keeps_requested_reports() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= A\n:revdat: 2024-01-01\n");
+    let cache = src.0.with_extension("cache");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+        .arg(&src.0)
+        .args(["--stdout", "--quiet", "--report-unknown-attributes", "--only-changed", cache.to_str().unwrap()])
+        .output()
+        .unwrap();
+    fs::remove_file(&cache).unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!("Unknown attributes: 1.\n    1  :revdat:\nDocuments    changed: 1.\n  {}\n", src.0.join("a.adoc").display()),
+    );
+}
+
+#[test]
+fn report_gaps_lists_missing_days() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= A\n:revdate: 2024-02-27\n");
+    src.write("b.adoc", b"= B\n:revdate: 2024-03-02\n");
+    src.write("c.adoc", b"= C\n:revdate: 2024-03-04\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+        .arg(&src.0)
+        .args(["--stdout", "--quiet", "--report-gaps"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "gap: 2024-02-28 .. 2024-03-01 (3 days)\ngap: 2024-03-03 .. 2024-03-03 (1 day)\n",
+    );
+}
+
+#[test]
+fn calendarignore_files_accumulate() {
+    let src = TempDir::new();
+    src.write(".calendarignore", b"# Work in progress\ndrafts*/\n*.tmp.adoc\n");
+    src.write("drafts/a.adoc", b"= Draft\n");
+    src.write("drafts-list.adoc", b"= Not a directory\n");
+    src.write("secret.adoc", b"= Top secret\n");
+    src.write("x.tmp.adoc", b"= Temporary\n");
+    src.write("sub/.calendarignore", b"secret.adoc\n");
+    src.write("sub/secret.adoc", b"= Sub secret\n");
+    src.write("sub/kept.adoc", b"= Sub kept\n");
+    src.write("sub/deeper/secret.adoc", b"= Deeper secret\n");
+    src.write("sub/deeper/y.tmp.adoc", b"= Deeper temporary\n");
+    src.write("sub/deeper/drafts/b.adoc", b"= Deeper draft\n");
+    src.write("sub/deeper/kept.adoc", b"= Deeper kept\n");
+
+    let docs = collect(&src.0, &collect_opts()).unwrap();
+    let mut found: Vec<&str> = docs.iter().filter_map(|doc| doc.title.as_deref()).collect();
+    found.sort();
+    assert_eq!(found, ["Deeper kept", "Not a directory", "Sub kept", "Top secret"]);
+}
+
+#[test]
+fn partial_date_bounds() {
+    let src = TempDir::new();
+    src.write("a.adoc", b"= 2023-12-31\n:revdate: 2023-12-31\n");
+    src.write("b.adoc", b"= 2024-02-29\n:revdate: 2024-02-29\n");
+    src.write("c.adoc", b"= 2024-03-01\n:revdate: 2024-03-01\n");
+    src.write("d.adoc", b"= 2024-05-31\n:revdate: 2024-05-31\n");
+    src.write("e.adoc", b"= 2024-06-01\n:revdate: 2024-06-01\n");
+    src.write("f.adoc", b"= 2024-12-31\n:revdate: 2024-12-31\n");
+    src.write("g.adoc", b"= 2025-01-01\n:revdate: 2025-01-01\n");
+
+    assert_eq!(titles(&run(&src.0, &["--start-date", "2024-06"])), ["2025-01-01", "2024-12-31", "2024-06-01"]);
+    assert_eq!(
+        titles(&run(&src.0, &["--end-date", "2024"])),
+        ["2024-12-31", "2024-06-01", "2024-05-31", "2024-03-01", "2024-02-29", "2023-12-31"],
+    );
+    // The end of a month bound is its last day, the 29th in a leap year.
+    assert_eq!(titles(&run(&src.0, &["--end-date", "2024-02"])), ["2024-02-29", "2023-12-31"]);
+    assert_eq!(titles(&run(&src.0, &["--start-date", "2024-02", "--end-date", "2024-02"])), ["2024-02-29"]);
+
+    let exit_code = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_calendar-fast")).arg(&src.0).arg("--stdout").args(args).status().unwrap().code()
+    };
+    assert_eq!(exit_code(&["--start-date", "2024-13"]), Some(2));
+    assert_eq!(exit_code(&["--end-date", "2024-13"]), Some(2));
+    assert_eq!(exit_code(&["--start-date", "2024-06-00"]), Some(2));
+    assert_eq!(exit_code(&["--end-date", "2024-06-00"]), Some(2));
+}
+
+#[test]
+fn verbose_says_why_documents_are_skipped() {
+    let src = TempDir::new();
+    let old = src.write("a.adoc", b"= Old\n:revdate: 2020-01-01\n");
+    let undated = src.write("b.adoc", b"= Undated\n");
+    let kept = src.write("c.adoc", b"= Kept\n:revdate: 2024-01-01\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_calendar-fast"))
+        .arg(&src.0)
+        .args(["--stdout", "--verbose", "--start-date", "2023"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!("skipped (outside date bounds): {}\n", old.display())), "{}", stderr);
+    assert!(stderr.contains(&format!("skipped (no revdate after filtering): {}\n", undated.display())));
+    assert!(stderr.contains(&format!("included: {}\n", kept.display())));
+}
+
+#[cfg(feature = "zip")]
+#[test]
+fn archived_documents_ignore_includes_and_image_rewriting() {
+    use calendar_fast::{parse_entry, read_zip, FoundFiles};
+
+    let archive = zip_archive(&[
+        ("a.adoc", b"= Included\n:revdate: 2024-02-01\n\ninclude::part.adoc[]\n"),
+        ("posts/b.adoc", b"= Images\n:revdate: 2024-01-01\n\nimage::cat.png[]\n"),
+    ]);
+
+    let mut found = FoundFiles::default();
+    read_zip(archive, &collect_opts().traverse, &mut found).unwrap();
+    let mut opts = collect_opts().parse;
+    opts.follow_includes = true;
+    opts.rewrite_image_paths = true;
+    let docs: Vec<Doc> = found.entries.iter().filter_map(|entry| parse_entry(entry, &opts).unwrap()).collect();
+
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].content, "= Images\n:revdate: 2024-01-01\n\nimage::cat.png[]\n");
+}